@@ -0,0 +1,484 @@
+use xrb::XClient;
+use xrb::models::*;
+
+use tiling::Geometry;
+
+use std::cmp::{max, min};
+
+/**
+ * Atoms interned at startup for EWMH and ICCCM properties and messages.
+ * Named exactly like the atoms themselves so they are easy to search for.
+ */
+#[allow(non_snake_case)]
+pub struct Atoms {
+    pub UTF8_STRING: u32,
+    pub WM_PROTOCOLS: u32,
+    pub WM_DELETE_WINDOW: u32,
+    pub WM_TAKE_FOCUS: u32,
+    pub _NET_WM_NAME: u32,
+    pub _NET_SUPPORTED: u32,
+    pub _NET_SUPPORTING_WM_CHECK: u32,
+    pub _NET_CLIENT_LIST: u32,
+    pub _NET_CLIENT_LIST_STACKING: u32,
+    pub _NET_ACTIVE_WINDOW: u32,
+    pub _NET_NUMBER_OF_DESKTOPS: u32,
+    pub _NET_CURRENT_DESKTOP: u32,
+    pub _NET_DESKTOP_NAMES: u32,
+    pub _NET_WM_DESKTOP: u32,
+    pub _NET_WM_STATE: u32,
+    pub _NET_WM_STATE_FULLSCREEN: u32,
+    pub _NET_WM_STATE_DEMANDS_ATTENTION: u32,
+    pub _NET_WM_WINDOW_TYPE: u32,
+    pub _NET_WM_WINDOW_TYPE_DIALOG: u32,
+    pub _NET_WM_WINDOW_TYPE_UTILITY: u32,
+    pub _NET_WM_WINDOW_TYPE_SPLASH: u32,
+    pub _NET_WM_WINDOW_TYPE_TOOLBAR: u32,
+    pub _NET_WM_WINDOW_TYPE_MENU: u32,
+    pub _NET_WM_WINDOW_TYPE_DOCK: u32,
+    pub _NET_WM_STRUT: u32,
+    pub _NET_WM_STRUT_PARTIAL: u32,
+    pub _NET_WORKAREA: u32
+}
+impl Atoms {
+    /** Interns every atom, waiting for each response. */
+    pub fn intern(client: &mut XClient) -> Atoms {
+        Atoms {
+            UTF8_STRING: intern_atom(client, "UTF8_STRING"),
+            WM_PROTOCOLS: intern_atom(client, "WM_PROTOCOLS"),
+            WM_DELETE_WINDOW: intern_atom(client, "WM_DELETE_WINDOW"),
+            WM_TAKE_FOCUS: intern_atom(client, "WM_TAKE_FOCUS"),
+            _NET_WM_NAME: intern_atom(client, "_NET_WM_NAME"),
+            _NET_SUPPORTED: intern_atom(client, "_NET_SUPPORTED"),
+            _NET_SUPPORTING_WM_CHECK: intern_atom(client, "_NET_SUPPORTING_WM_CHECK"),
+            _NET_CLIENT_LIST: intern_atom(client, "_NET_CLIENT_LIST"),
+            _NET_CLIENT_LIST_STACKING: intern_atom(client, "_NET_CLIENT_LIST_STACKING"),
+            _NET_ACTIVE_WINDOW: intern_atom(client, "_NET_ACTIVE_WINDOW"),
+            _NET_NUMBER_OF_DESKTOPS: intern_atom(client, "_NET_NUMBER_OF_DESKTOPS"),
+            _NET_CURRENT_DESKTOP: intern_atom(client, "_NET_CURRENT_DESKTOP"),
+            _NET_DESKTOP_NAMES: intern_atom(client, "_NET_DESKTOP_NAMES"),
+            _NET_WM_DESKTOP: intern_atom(client, "_NET_WM_DESKTOP"),
+            _NET_WM_STATE: intern_atom(client, "_NET_WM_STATE"),
+            _NET_WM_STATE_FULLSCREEN: intern_atom(client, "_NET_WM_STATE_FULLSCREEN"),
+            _NET_WM_STATE_DEMANDS_ATTENTION: intern_atom(client, "_NET_WM_STATE_DEMANDS_ATTENTION"),
+            _NET_WM_WINDOW_TYPE: intern_atom(client, "_NET_WM_WINDOW_TYPE"),
+            _NET_WM_WINDOW_TYPE_DIALOG: intern_atom(client, "_NET_WM_WINDOW_TYPE_DIALOG"),
+            _NET_WM_WINDOW_TYPE_UTILITY: intern_atom(client, "_NET_WM_WINDOW_TYPE_UTILITY"),
+            _NET_WM_WINDOW_TYPE_SPLASH: intern_atom(client, "_NET_WM_WINDOW_TYPE_SPLASH"),
+            _NET_WM_WINDOW_TYPE_TOOLBAR: intern_atom(client, "_NET_WM_WINDOW_TYPE_TOOLBAR"),
+            _NET_WM_WINDOW_TYPE_MENU: intern_atom(client, "_NET_WM_WINDOW_TYPE_MENU"),
+            _NET_WM_WINDOW_TYPE_DOCK: intern_atom(client, "_NET_WM_WINDOW_TYPE_DOCK"),
+            _NET_WM_STRUT: intern_atom(client, "_NET_WM_STRUT"),
+            _NET_WM_STRUT_PARTIAL: intern_atom(client, "_NET_WM_STRUT_PARTIAL"),
+            _NET_WORKAREA: intern_atom(client, "_NET_WORKAREA")
+        }
+    }
+
+    /** Returns the hints published in _NET_SUPPORTED. */
+    pub fn supported(&self) -> Vec<u32> {
+        vec![
+            self._NET_WM_NAME,
+            self._NET_SUPPORTED,
+            self._NET_SUPPORTING_WM_CHECK,
+            self._NET_CLIENT_LIST,
+            self._NET_CLIENT_LIST_STACKING,
+            self._NET_ACTIVE_WINDOW,
+            self._NET_NUMBER_OF_DESKTOPS,
+            self._NET_CURRENT_DESKTOP,
+            self._NET_DESKTOP_NAMES,
+            self._NET_WM_DESKTOP,
+            self._NET_WM_STATE,
+            self._NET_WM_STATE_FULLSCREEN,
+            self._NET_WM_STATE_DEMANDS_ATTENTION,
+            self._NET_WM_WINDOW_TYPE,
+            self._NET_WM_WINDOW_TYPE_DIALOG,
+            self._NET_WM_WINDOW_TYPE_UTILITY,
+            self._NET_WM_WINDOW_TYPE_SPLASH,
+            self._NET_WM_WINDOW_TYPE_TOOLBAR,
+            self._NET_WM_WINDOW_TYPE_MENU,
+            self._NET_WM_WINDOW_TYPE_DOCK,
+            self._NET_WM_STRUT,
+            self._NET_WM_STRUT_PARTIAL,
+            self._NET_WORKAREA
+        ]
+    }
+
+    /** Returns the _NET_WM_WINDOW_TYPEs that float instead of being tiled. */
+    pub fn floating_types(&self) -> Vec<u32> {
+        vec![
+            self._NET_WM_WINDOW_TYPE_DIALOG,
+            self._NET_WM_WINDOW_TYPE_UTILITY,
+            self._NET_WM_WINDOW_TYPE_SPLASH,
+            self._NET_WM_WINDOW_TYPE_TOOLBAR,
+            self._NET_WM_WINDOW_TYPE_MENU
+        ]
+    }
+}
+
+/**
+ * The parts of a window's WM_NORMAL_HINTS the window manager uses, as (width, height).
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SizeHints {
+    pub min: Option<(u16, u16)>,
+    pub max: Option<(u16, u16)>,
+    pub inc: Option<(u16, u16)>, // Resize increments, such as a terminal's cell size
+    pub base: Option<(u16, u16)>, // Size increments are counted from
+    pub min_aspect: Option<(u16, u16)>, // Smallest width / height ratio, as (numerator, denominator)
+    pub max_aspect: Option<(u16, u16)>
+}
+impl SizeHints {
+    /** Reads the given window's WM_NORMAL_HINTS. Hints the client didn't set are None. */
+    pub fn get(client: &mut XClient, wid: u32) -> SizeHints {
+        let values = get_property32(client, wid, DefaultAtom::WmNormalHints.val(), DefaultAtom::WmSizeHints.val());
+        let flags = values.get(0).cloned().unwrap_or(0);
+        let pair = |flag: u32, index: usize| {
+            if flags & flag != 0 && values.len() > index + 1 {
+                Some((values[index] as u16, values[index + 1] as u16))
+            } else {
+                None
+            }
+        };
+
+        SizeHints {
+            min: pair(16, 5), // PMinSize
+            max: pair(32, 7), // PMaxSize
+            inc: pair(64, 9), // PResizeInc
+            base: pair(256, 15), // PBaseSize
+            min_aspect: pair(128, 11), // PAspect
+            max_aspect: pair(128, 13)
+        }
+    }
+
+    /**
+     * Returns the largest size that fits in the given size and follows the hints, as (width, height).
+     * The minimum size wins over the given size, so the result may be bigger.
+     */
+    pub fn constrain(&self, width: u16, height: u16) -> (u16, u16) {
+        // ICCCM: the base size defaults to the minimum size, and the minimum size to the base size
+        let (min_width, min_height) = self.min.or(self.base).unwrap_or((1, 1));
+        let (base_width, base_height) = self.base.or(self.min).unwrap_or((0, 0));
+        let (mut width, mut height) = (width as u32, height as u32);
+
+        match self.max {
+            Some((max_width, max_height)) => {
+                if max_width > 0 {
+                    width = min(width, max_width as u32);
+                }
+                if max_height > 0 {
+                    height = min(height, max_height as u32);
+                }
+            },
+            None => ()
+        };
+
+        // Ratios are compared without the base size, as ICCCM asks
+        match self.max_aspect {
+            Some((x, y)) if x > 0 && y > 0 => {
+                let (w, h) = (width.saturating_sub(base_width as u32), height.saturating_sub(base_height as u32));
+                if w * y as u32 > h * x as u32 {
+                    width = base_width as u32 + h * x as u32 / y as u32;
+                }
+            },
+            _ => ()
+        };
+        match self.min_aspect {
+            Some((x, y)) if x > 0 && y > 0 => {
+                let (w, h) = (width.saturating_sub(base_width as u32), height.saturating_sub(base_height as u32));
+                if w * y as u32 < h * x as u32 {
+                    height = base_height as u32 + w * y as u32 / x as u32;
+                }
+            },
+            _ => ()
+        };
+
+        match self.inc {
+            Some((inc_width, inc_height)) => {
+                if inc_width > 0 && width > base_width as u32 {
+                    width -= (width - base_width as u32) % inc_width as u32;
+                }
+                if inc_height > 0 && height > base_height as u32 {
+                    height -= (height - base_height as u32) % inc_height as u32;
+                }
+            },
+            None => ()
+        };
+
+        width = max(width, max(min_width as u32, 1));
+        height = max(height, max(min_height as u32, 1));
+        return (min(width, u16::max_value() as u32) as u16, min(height, u16::max_value() as u32) as u16);
+    }
+
+    /** Returns true if the window can't be resized. */
+    pub fn is_fixed(&self) -> bool {
+        return self.min.is_some() && self.min == self.max;
+    }
+}
+
+/**
+ * Space a dock reserves at the edges of the screen, from its _NET_WM_STRUT_PARTIAL (or _NET_WM_STRUT).
+ * Widths are measured from the edges of the root window, ranges are (first, last) pixel along that edge.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Strut {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+    pub left_range: (u32, u32), // Rows the left strut covers
+    pub right_range: (u32, u32),
+    pub top_range: (u32, u32), // Columns the top strut covers
+    pub bottom_range: (u32, u32)
+}
+impl Strut {
+    /** Reads the given window's strut. Windows without one reserve nothing. */
+    pub fn get(client: &mut XClient, atoms: &Atoms, wid: u32) -> Strut {
+        let values = get_property32(client, wid, atoms._NET_WM_STRUT_PARTIAL, DefaultAtom::Cardinal.val());
+        if values.len() >= 12 {
+            return Strut {
+                left: values[0],
+                right: values[1],
+                top: values[2],
+                bottom: values[3],
+                left_range: (values[4], values[5]),
+                right_range: (values[6], values[7]),
+                top_range: (values[8], values[9]),
+                bottom_range: (values[10], values[11])
+            };
+        }
+
+        // The older property covers whole edges
+        let mut values = get_property32(client, wid, atoms._NET_WM_STRUT, DefaultAtom::Cardinal.val());
+        values.resize(4, 0);
+        let whole = (0, u32::max_value());
+        return Strut {
+            left: values[0],
+            right: values[1],
+            top: values[2],
+            bottom: values[3],
+            left_range: whole,
+            right_range: whole,
+            top_range: whole,
+            bottom_range: whole
+        };
+    }
+
+    /**
+     * Returns how much of the given monitor this strut covers from its left, right, top and bottom edges.
+     * screen_width and screen_height are the size of the root window, which right and bottom struts are measured from.
+     */
+    pub fn reserved(&self, monitor: Geometry, screen_width: u32, screen_height: u32) -> (u16, u16, u16, u16) {
+        let (x, y) = (monitor.x as i64, monitor.y as i64);
+        let (width, height) = (monitor.width as i64, monitor.height as i64);
+        let overlaps = |range: (u32, u32), start: i64, length: i64| (range.0 as i64) < start + length && range.1 as i64 >= start;
+        let clamp = |covered: i64, size: i64| min(max(covered, 0), size) as u16;
+
+        let left = if overlaps(self.left_range, y, height) {clamp(self.left as i64 - x, width)} else {0};
+        let right = if overlaps(self.right_range, y, height) {clamp(x + width - (screen_width as i64 - self.right as i64), width)} else {0};
+        let top = if overlaps(self.top_range, x, width) {clamp(self.top as i64 - y, height)} else {0};
+        let bottom = if overlaps(self.bottom_range, x, width) {clamp(y + height - (screen_height as i64 - self.bottom as i64), height)} else {0};
+        return (left, right, top, bottom);
+    }
+}
+
+/**
+ * Interns the given atom, waiting for the response.
+ */
+pub fn intern_atom(client: &mut XClient, name: &str) -> u32 {
+    let seq = client.intern_atom(name, false);
+    match client.wait_for_response(seq) {
+        ServerResponse::Error(err, _) => panic!("Failed to get {} atom: {:?}", name, err),
+        ServerResponse::Reply(reply, _) => match reply {
+            ServerReply::InternAtom { atom }
+                => atom,
+            _ => unreachable!()
+        },
+        _ => unreachable!()
+    }
+}
+
+/**
+ * Returns a property made of 32 bit values (CARDINAL, WINDOW, ATOM...) on the given window.
+ * Returns an empty list if the property isn't set, has another format, or the window is gone.
+ */
+pub fn get_property32(client: &mut XClient, wid: u32, property: u32, property_type: u32) -> Vec<u32> {
+    let seq = client.get_property(false, wid, property, property_type, 0, 1024);
+    match client.wait_for_response(seq) {
+        ServerResponse::Reply(ServerReply::GetProperty { format, value, .. }, _) => {
+            if format != 32 {
+                return vec![];
+            }
+
+            return value.chunks(4)
+                .filter(|bytes| bytes.len() == 4)
+                .map(|bytes| bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24)
+                .collect();
+        },
+        _ => return vec![]
+    };
+}
+
+/**
+ * Replaces a property made of 32 bit values (CARDINAL, WINDOW, ATOM...) on the given window.
+ */
+pub fn set_property32(client: &mut XClient, wid: u32, property: u32, property_type: u32, values: &[u32]) {
+    let mut data = Vec::with_capacity(values.len() * 4);
+    for value in values.iter() {
+        data.push(*value as u8);
+        data.push((*value >> 8) as u8);
+        data.push((*value >> 16) as u8);
+        data.push((*value >> 24) as u8);
+    }
+
+    client.change_property(PropertyChangeMode::Replace, wid, property, property_type, 32, &data);
+}
+
+/**
+ * Replaces a UTF8_STRING property on the given window.
+ */
+pub fn set_utf8_property(client: &mut XClient, atoms: &Atoms, wid: u32, property: u32, value: &str) {
+    client.change_property(PropertyChangeMode::Replace, wid, property, atoms.UTF8_STRING, 8, value.as_bytes());
+}
+
+/**
+ * Sends a ClientMessage with up to five 32 bit values to the given window.
+ */
+pub fn send_client_message(client: &mut XClient, wid: u32, message_type: u32, values: &[u32]) {
+    let mut data = [0u8; 20];
+    for (i, value) in values.iter().take(5).enumerate() {
+        data[i * 4] = *value as u8;
+        data[i * 4 + 1] = (*value >> 8) as u8;
+        data[i * 4 + 2] = (*value >> 16) as u8;
+        data[i * 4 + 3] = (*value >> 24) as u8;
+    }
+
+    client.send_event(&ServerEvent::ClientMessage {
+        format: 32,
+        window: wid,
+        message_type,
+        data
+    }, false, wid, &vec![]);
+}
+
+/**
+ * Returns the index-th 32 bit value of a ClientMessage's data.
+ */
+pub fn client_message_value(data: &[u8], index: usize) -> u32 {
+    let i = index * 4;
+    return data[i] as u32 | (data[i + 1] as u32) << 8 | (data[i + 2] as u32) << 16 | (data[i + 3] as u32) << 24;
+}
+
+/**
+ * Returns the new value of a _NET_WM_STATE flag after a _NET_WM_STATE ClientMessage with the given action.
+ */
+pub fn wm_state_action(current: bool, action: u32) -> bool {
+    match action {
+        0 => false, // _NET_WM_STATE_REMOVE
+        1 => true, // _NET_WM_STATE_ADD
+        2 => !current, // _NET_WM_STATE_TOGGLE
+        _ => current
+    }
+}
+
+/**
+ * Creates the window _NET_SUPPORTING_WM_CHECK points to, and advertises the supported hints on the root.
+ * The window is never mapped. Returns its ID.
+ */
+pub fn create_check_window(client: &mut XClient, atoms: &Atoms, root: u32) -> u32 {
+    let check = Window::create(
+        client,
+        root,
+        0,
+        -1,
+        -1,
+        1,
+        1,
+        0,
+        WindowInputType::InputOnly,
+        0, // CopyFromParent
+        vec![]
+    );
+
+    set_property32(client, root, atoms._NET_SUPPORTING_WM_CHECK, DefaultAtom::Window.val(), &[check.wid]);
+    set_property32(client, check.wid, atoms._NET_SUPPORTING_WM_CHECK, DefaultAtom::Window.val(), &[check.wid]);
+    set_utf8_property(client, atoms, check.wid, atoms._NET_WM_NAME, "t4lwm");
+    set_property32(client, root, atoms._NET_SUPPORTED, DefaultAtom::Atom.val(), &atoms.supported());
+
+    return check.wid;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hints() -> SizeHints {
+        SizeHints { min: None, max: None, inc: None, base: None, min_aspect: None, max_aspect: None }
+    }
+
+    #[test]
+    fn constrain_without_hints_keeps_the_size() {
+        assert_eq!(hints().constrain(100, 50), (100, 50));
+        assert_eq!(hints().constrain(0, 0), (1, 1));
+    }
+
+    #[test]
+    fn constrain_applies_the_maximum() {
+        let hints = SizeHints { max: Some((80, 0)), ..hints() }; // 0 means no maximum
+        assert_eq!(hints.constrain(100, 50), (80, 50));
+    }
+
+    #[test]
+    fn constrain_minimum_wins() {
+        let hints = SizeHints { min: Some((200, 10)), max: Some((150, 150)), ..hints() };
+        assert_eq!(hints.constrain(100, 50), (200, 50));
+    }
+
+    #[test]
+    fn constrain_counts_increments_from_the_base() {
+        let hints = SizeHints { inc: Some((10, 20)), base: Some((4, 2)), ..hints() };
+        assert_eq!(hints.constrain(100, 50), (94, 42));
+    }
+
+    #[test]
+    fn constrain_keeps_the_aspect_ratio() {
+        let square = SizeHints { min_aspect: Some((1, 1)), max_aspect: Some((1, 1)), ..hints() };
+        assert_eq!(square.constrain(100, 50), (50, 50));
+
+        let wide = SizeHints { min_aspect: Some((2, 1)), ..hints() };
+        assert_eq!(wide.constrain(100, 100), (100, 50));
+    }
+
+    #[test]
+    fn fixed_size_needs_equal_minimum_and_maximum() {
+        assert!(SizeHints { min: Some((10, 10)), max: Some((10, 10)), ..hints() }.is_fixed());
+        assert!(!SizeHints { min: Some((10, 10)), max: Some((20, 10)), ..hints() }.is_fixed());
+        assert!(!hints().is_fixed());
+    }
+
+    #[test]
+    fn wm_state_action_removes_adds_and_toggles() {
+        assert_eq!(wm_state_action(true, 0), false);
+        assert_eq!(wm_state_action(false, 1), true);
+        assert_eq!(wm_state_action(true, 2), false);
+        assert_eq!(wm_state_action(false, 2), true);
+    }
+
+    fn strut() -> Strut {
+        Strut { left: 0, right: 0, top: 0, bottom: 0, left_range: (0, 0), right_range: (0, 0), top_range: (0, 0), bottom_range: (0, 0) }
+    }
+
+    #[test]
+    fn partial_struts_only_reserve_on_monitors_they_cover() {
+        // Two 1920x1080 monitors side by side
+        let left = Geometry { x: 0, y: 0, width: 1920, height: 1080 };
+        let right = Geometry { x: 1920, y: 0, width: 1920, height: 1080 };
+
+        let bar = Strut { top: 30, top_range: (0, 1919), ..strut() };
+        assert_eq!(bar.reserved(left, 3840, 1080), (0, 0, 30, 0));
+        assert_eq!(bar.reserved(right, 3840, 1080), (0, 0, 0, 0));
+
+        // Right struts are measured from the right of the root window
+        let dock = Strut { right: 50, right_range: (0, 1079), ..strut() };
+        assert_eq!(dock.reserved(left, 3840, 1080), (0, 0, 0, 0));
+        assert_eq!(dock.reserved(right, 3840, 1080), (0, 50, 0, 0));
+    }
+}
@@ -0,0 +1,230 @@
+use xrb::XClient;
+use xrb::models::*;
+
+use tiling::{Direction, TiledDirection, TiledMode};
+
+/** Something a key binding does. */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Spawn(String), // Runs the given command, split on whitespace
+    Close, // Closes the focused window, or kills it if it is already closing
+    Workspace(u32), // Switches to the workspace with the given ID, creating it if needed
+    MoveToWorkspace(u32), // Moves the focused window to the workspace with the given ID, creating it if needed
+    FocusMonitor(i32), // Focuses the monitor this many places after the current one, wrapping around
+    MoveToMonitor(i32), // Moves the focused window to the workspace shown on the monitor this many places after the current one
+    Focus(Direction),
+    Move(Direction),
+    Resize(Direction), // Right and Down grow the focused window, Left and Up shrink it
+    ResizeMode, // Uses Settings::resize_bindings until ExitResizeMode
+    ExitResizeMode,
+    ContainerMode(TiledMode),
+    TileDirection(TiledDirection), // The direction new windows are split in
+    CycleLayout,
+    ToggleFloating,
+    ToggleFullscreen,
+    FocusUrgent
+}
+
+/** A key combination and what it does. */
+#[derive(Debug, Clone)]
+pub struct KeyBinding {
+    pub modifiers: Vec<KeyButton>,
+    pub keysym: u32,
+    pub action: Action
+}
+impl KeyBinding {
+    /**
+     * Creates a binding for the named key ("Return", "q", "1"...), see keysym_from_name.
+     * Panics if the name is unknown.
+     */
+    pub fn new(modifiers: &[KeyButton], key: &str, action: Action) -> KeyBinding {
+        let keysym = match keysym_from_name(key) {
+            Some(keysym) => keysym,
+            None => panic!("Unknown key in binding: {}", key)
+        };
+
+        KeyBinding {
+            modifiers: modifiers.to_vec(),
+            keysym,
+            action
+        }
+    }
+
+    /** Returns the modifiers as a mask, for GrabKey. */
+    pub fn modifier_mask(&self) -> u16 {
+        return self.modifiers.iter().fold(0, |mask, modifier| mask | modifier.val());
+    }
+
+    /**
+     * Returns true if this binding is for the given keysym and exactly the pressed modifiers.
+     * Lock modifiers (Caps Lock, Num Lock...) are ignored.
+     */
+    pub fn matches<F: Fn(&KeyButton) -> bool>(&self, keysym: u32, pressed: F) -> bool {
+        if keysym != self.keysym {
+            return false;
+        }
+
+        let modifiers = [KeyButton::Shift, KeyButton::Control, KeyButton::Mod1, KeyButton::Mod4];
+        return modifiers.iter().all(|modifier| self.modifiers.contains(modifier) == pressed(modifier));
+    }
+}
+
+/**
+ * Returns the action bound to the given keysym and pressed modifiers, if any.
+ */
+pub fn find_action<F: Fn(&KeyButton) -> bool>(bindings: &[KeyBinding], keysym: u32, pressed: F) -> Option<&Action> {
+    return bindings.iter().find(|binding| binding.matches(keysym, &pressed)).map(|binding| &binding.action);
+}
+
+/** The server's keycode to keysym table. */
+pub struct Keymap {
+    min_keycode: u8,
+    per_keycode: usize, // Keysyms per keycode, one for each modifier column
+    keysyms: Vec<u32>
+}
+impl Keymap {
+    /** Fetches the keyboard mapping, waiting for the response. */
+    pub fn get(client: &mut XClient) -> Keymap {
+        let min_keycode = client.info.min_keycode;
+        let count = client.info.max_keycode - min_keycode + 1;
+        let seq = client.get_keyboard_mapping(min_keycode, count);
+
+        match client.wait_for_response(seq) {
+            ServerResponse::Error(err, _) => panic!("Failed to get the keyboard mapping: {:?}", err),
+            ServerResponse::Reply(reply, _) => match reply {
+                ServerReply::GetKeyboardMapping { keysyms_per_keycode, keysyms }
+                    => Keymap { min_keycode, per_keycode: keysyms_per_keycode as usize, keysyms },
+                _ => unreachable!()
+            },
+            _ => unreachable!()
+        }
+    }
+
+    /** Returns the keysym a keycode produces without modifiers, or 0 (NoSymbol). */
+    pub fn keysym(&self, keycode: u8) -> u32 {
+        if keycode < self.min_keycode || self.per_keycode == 0 {
+            return 0;
+        }
+
+        let index = (keycode - self.min_keycode) as usize * self.per_keycode;
+        return self.keysyms.get(index).cloned().unwrap_or(0);
+    }
+
+    /** Returns every keycode that produces the given keysym without modifiers. */
+    pub fn keycodes(&self, keysym: u32) -> Vec<u8> {
+        if self.per_keycode == 0 {
+            return vec![];
+        }
+
+        return self.keysyms.chunks(self.per_keycode)
+            .enumerate()
+            .filter(|&(_, keysyms)| keysyms[0] == keysym)
+            .map(|(i, _)| self.min_keycode + i as u8)
+            .collect();
+    }
+}
+
+/**
+ * Returns the keysym with the given name, as used by xmodmap: "Return", "q", "F1", "comma"...
+ * Only the keys likely to be bound are known. Letters are always lowercase, as bindings use the unshifted keysym.
+ */
+pub fn keysym_from_name(name: &str) -> Option<u32> {
+    // Printable ASCII keysyms are the characters themselves
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_graphic() => return Some(c.to_ascii_lowercase() as u32),
+        _ => ()
+    };
+
+    // F1 to F35
+    if name.len() > 1 && name.starts_with('F') {
+        match name[1..].parse::<u32>() {
+            Ok(n) if n >= 1 && n <= 35 => return Some(0xFFBD + n),
+            _ => ()
+        };
+    }
+
+    let keysym = match name {
+        "space" => 0x0020,
+        "apostrophe" => 0x0027,
+        "comma" => 0x002C,
+        "minus" => 0x002D,
+        "period" => 0x002E,
+        "slash" => 0x002F,
+        "semicolon" => 0x003B,
+        "equal" => 0x003D,
+        "bracketleft" => 0x005B,
+        "backslash" => 0x005C,
+        "bracketright" => 0x005D,
+        "grave" => 0x0060,
+        "BackSpace" => 0xFF08,
+        "Tab" => 0xFF09,
+        "Return" => 0xFF0D,
+        "Escape" => 0xFF1B,
+        "Home" => 0xFF50,
+        "Left" => 0xFF51,
+        "Up" => 0xFF52,
+        "Right" => 0xFF53,
+        "Down" => 0xFF54,
+        "Prior" | "Page_Up" => 0xFF55,
+        "Next" | "Page_Down" => 0xFF56,
+        "End" => 0xFF57,
+        "Print" => 0xFF61,
+        "Insert" => 0xFF63,
+        "Delete" => 0xFFFF,
+        _ => return None
+    };
+
+    return Some(keysym);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keysym_from_name_knows_characters() {
+        assert_eq!(keysym_from_name("a"), Some(0x61));
+        assert_eq!(keysym_from_name("Q"), Some(0x71)); // Bindings use the unshifted keysym
+        assert_eq!(keysym_from_name("1"), Some(0x31));
+        assert_eq!(keysym_from_name("comma"), Some(0x2C));
+        assert_eq!(keysym_from_name("space"), Some(0x20));
+    }
+
+    #[test]
+    fn keysym_from_name_knows_function_keys() {
+        assert_eq!(keysym_from_name("F1"), Some(0xFFBE));
+        assert_eq!(keysym_from_name("F35"), Some(0xFFE0));
+        assert_eq!(keysym_from_name("F36"), None);
+        assert_eq!(keysym_from_name("F0"), None);
+    }
+
+    #[test]
+    fn keysym_from_name_knows_special_keys() {
+        assert_eq!(keysym_from_name("Return"), Some(0xFF0D));
+        assert_eq!(keysym_from_name("Escape"), Some(0xFF1B));
+        assert_eq!(keysym_from_name("Page_Up"), keysym_from_name("Prior"));
+        assert_eq!(keysym_from_name("Nope"), None);
+        assert_eq!(keysym_from_name(""), None);
+    }
+
+    #[test]
+    fn bindings_match_exact_modifiers() {
+        let binding = KeyBinding::new(&[KeyButton::Mod4, KeyButton::Shift], "q", Action::Close);
+
+        assert!(binding.matches(0x71, |modifier| *modifier == KeyButton::Mod4 || *modifier == KeyButton::Shift));
+        assert!(!binding.matches(0x71, |modifier| *modifier == KeyButton::Mod4));
+        assert!(!binding.matches(0x72, |modifier| *modifier == KeyButton::Mod4 || *modifier == KeyButton::Shift));
+    }
+
+    #[test]
+    fn keymap_uses_the_unmodified_column() {
+        let keymap = Keymap { min_keycode: 8, per_keycode: 2, keysyms: vec![0x61, 0x41, 0x62, 0x42, 0x61, 0x41] };
+
+        assert_eq!(keymap.keysym(7), 0);
+        assert_eq!(keymap.keysym(9), 0x62);
+        assert_eq!(keymap.keysym(11), 0);
+        assert_eq!(keymap.keycodes(0x61), vec![8, 10]);
+        assert_eq!(keymap.keycodes(0x41), vec![]);
+    }
+}
@@ -0,0 +1,503 @@
+use manager::ManagedWindow;
+use settings::Settings;
+use tiling::{split_even, Direction, Geometry, TileAssignment, Tiled, TiledDirection, TiledMode, MIN_TILE_SIZE};
+
+use xrb::XClient;
+
+/** The available layouts, in the order they are cycled through. */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LayoutKind {
+    Tiled,
+    MasterStack,
+    Columns,
+    Grid,
+    Spiral,
+    Monocle
+}
+impl LayoutKind {
+    /** Returns the layout that comes after this one when cycling. */
+    pub fn next(&self) -> LayoutKind {
+        match *self {
+            LayoutKind::Tiled => LayoutKind::MasterStack,
+            LayoutKind::MasterStack => LayoutKind::Columns,
+            LayoutKind::Columns => LayoutKind::Grid,
+            LayoutKind::Grid => LayoutKind::Spiral,
+            LayoutKind::Spiral => LayoutKind::Monocle,
+            LayoutKind::Monocle => LayoutKind::Tiled
+        }
+    }
+
+    /** Creates an empty layout of this kind. */
+    pub fn create(&self, direction: TiledDirection) -> Box<dyn Layout> {
+        match *self {
+            LayoutKind::Tiled => Box::new(Tiled::new_0(direction)),
+            kind => Box::new(FlatLayout::new(kind))
+        }
+    }
+}
+
+/**
+ * Arranges the windows of a workspace.
+ * Layouts own their windows. Only layout() decides where they go, and tile() sends that to the X server.
+ */
+pub trait Layout {
+    /** Returns which kind of layout this is. */
+    fn kind(&self) -> LayoutKind;
+
+    /** Adds a window next to the currently focused window and focuses it. */
+    fn add(&mut self, window: ManagedWindow, direction: TiledDirection);
+
+    /** Removes the window given its ID, returning it if it was found. */
+    fn remove(&mut self, wid: u32) -> Option<ManagedWindow>;
+
+    /** Removes and returns every window, in order. Used when switching layouts. */
+    fn take_all(&mut self) -> Vec<ManagedWindow>;
+
+    /** Returns every window, in order. */
+    fn windows(&self) -> Vec<&ManagedWindow>;
+
+    /** Returns the given window given its ID (or its wrapper's ID) */
+    fn get_window(&self, wid: u32) -> Option<&ManagedWindow>;
+
+    /** Returns the given window given its ID (or its wrapper's ID) */
+    fn get_window_mut(&mut self, wid: u32) -> Option<&mut ManagedWindow>;
+
+    /** Makes the given window focused. Returns true if it was found. */
+    fn set_focused(&mut self, wid: u32) -> bool;
+
+    /** Returns the currently focused Window, or None */
+    fn get_focused(&self) -> Option<&ManagedWindow>;
+
+    /** Swaps two windows, each taking the other's place and size. Returns true if both were found. */
+    fn swap_windows(&mut self, first: u32, second: u32) -> bool;
+
+    /** Returns the currently focused Window, or None */
+    fn get_focused_mut(&mut self) -> Option<&mut ManagedWindow>;
+
+    /**
+     * Grows the focused window by amount pixels (or shrinks it if amount is negative) along the given direction.
+     * Returns true if anything changed.
+     */
+    fn resize(&mut self, direction: TiledDirection, amount: i16, area: Geometry) -> bool;
+
+    /**
+     * Returns true if resize() would grow the given window by moving its left or top edge (because it takes space from the window before it).
+     * Returns None if resize() wouldn't change anything for it along the given direction.
+     */
+    fn resizes_backwards(&self, _wid: u32, _direction: TiledDirection) -> Option<bool> {
+        return None;
+    }
+
+    /**
+     * Moves the focused window in the given direction. neighbour is the window find_neighbour() picked in that direction.
+     * Returns true if anything changed.
+     */
+    fn move_focused(&mut self, direction: Direction, neighbour: Option<u32>) -> bool;
+
+    /** Computes where every window should be placed to fill the given area, without talking to the X server. */
+    fn layout(&self, settings: &Settings, area: Geometry) -> Vec<TileAssignment>;
+
+    /** Marks this layout as needing to be re-tiled. */
+    fn mark_dirty(&mut self);
+
+    /** Returns true if this layout needs to be re-tiled. */
+    fn is_dirty(&self) -> bool;
+
+    /** Marks this layout as tiled. */
+    fn mark_clean(&mut self);
+
+    /** Sets how the focused window's container shows its children. Returns true if anything changed. */
+    fn set_mode(&mut self, _mode: TiledMode) -> bool {
+        return false; // Only Tiled has containers
+    }
+
+    /** Positions the windows to fill the given area, if anything changed since the last time (or force is set). */
+    fn tile(&mut self, client: &mut XClient, settings: &Settings, area: Geometry, force: bool) {
+        if !self.is_dirty() && !force {
+            return;
+        }
+
+        let assignments = self.layout(settings, area);
+        self.apply(client, &assignments);
+        self.mark_clean();
+    }
+
+    /** Sends the given assignments to the X server. */
+    fn apply(&mut self, client: &mut XClient, assignments: &Vec<TileAssignment>) {
+        for assignment in assignments.iter() {
+            match self.get_window_mut(assignment.wid) {
+                Some(wrapped) => wrapped.apply(client, assignment),
+                None => ()
+            };
+        }
+    }
+}
+
+/**
+ * A layout that keeps its windows in a list and arranges them with a fixed algorithm.
+ * Used for every LayoutKind except Tiled.
+ */
+pub struct FlatLayout {
+    kind: LayoutKind,
+    windows: Vec<ManagedWindow>,
+    master_ratio: f32, // MasterStack only: how much of the width the master window takes
+    dirty: bool
+}
+impl FlatLayout {
+    pub fn new(kind: LayoutKind) -> FlatLayout {
+        FlatLayout {
+            kind,
+            windows: Vec::new(),
+            master_ratio: 0.5,
+            dirty: true
+        }
+    }
+
+    /** Returns the index of the focused window. */
+    fn focused_index(&self) -> Option<usize> {
+        return self.windows.iter().position(|wrapped| wrapped.focused);
+    }
+
+    /** Returns the area of each window, in order. */
+    fn areas(&self, area: Geometry) -> Vec<Geometry> {
+        let count = self.windows.len();
+
+        match self.kind {
+            LayoutKind::MasterStack => master_stack(count, area, self.master_ratio),
+            LayoutKind::Grid => grid(count, area),
+            LayoutKind::Spiral => spiral(count, area),
+            LayoutKind::Monocle => vec![area; count],
+            _ => columns(count, area)
+        }
+    }
+}
+impl Layout for FlatLayout {
+    fn kind(&self) -> LayoutKind {
+        return self.kind;
+    }
+
+    fn add(&mut self, mut window: ManagedWindow, _direction: TiledDirection) {
+        let index = match self.focused_index() {
+            Some(index) => {
+                self.windows[index].focused = false;
+                index + 1
+            },
+            None => self.windows.len()
+        };
+
+        window.focused = true;
+        self.windows.insert(index, window);
+        self.mark_dirty();
+    }
+
+    fn remove(&mut self, wid: u32) -> Option<ManagedWindow> {
+        let index = match self.windows.iter().position(|wrapped| wrapped.window.wid == wid) {
+            Some(index) => index,
+            None => return None
+        };
+
+        let removed = self.windows.remove(index);
+
+        // Focus the window that took its place
+        if removed.focused && self.windows.len() > 0 {
+            let len = self.windows.len();
+            self.windows[if index < len { index } else { len - 1 }].focused = true;
+        }
+
+        self.mark_dirty();
+        return Some(removed);
+    }
+
+    fn take_all(&mut self) -> Vec<ManagedWindow> {
+        self.mark_dirty();
+        return self.windows.drain(..).collect();
+    }
+
+    fn windows(&self) -> Vec<&ManagedWindow> {
+        return self.windows.iter().collect();
+    }
+
+    fn get_window(&self, wid: u32) -> Option<&ManagedWindow> {
+        return self.windows.iter().find(|wrapped| wrapped.window.wid == wid || wrapped.wrapper.wid == wid);
+    }
+
+    fn get_window_mut(&mut self, wid: u32) -> Option<&mut ManagedWindow> {
+        return self.windows.iter_mut().find(|wrapped| wrapped.window.wid == wid || wrapped.wrapper.wid == wid);
+    }
+
+    fn set_focused(&mut self, wid: u32) -> bool {
+        let mut found = false;
+
+        for wrapped in self.windows.iter_mut() {
+            wrapped.focused = wrapped.window.wid == wid || wrapped.wrapper.wid == wid;
+            if wrapped.focused {
+                found = true;
+            }
+        }
+
+        self.mark_dirty();
+        return found;
+    }
+
+    fn swap_windows(&mut self, first: u32, second: u32) -> bool {
+        let first = self.windows.iter().position(|wrapped| wrapped.window.wid == first);
+        let second = self.windows.iter().position(|wrapped| wrapped.window.wid == second);
+
+        match (first, second) {
+            (Some(first), Some(second)) if first != second => {
+                self.windows.swap(first, second);
+                self.mark_dirty();
+                return true;
+            },
+            _ => return false
+        };
+    }
+
+    fn get_focused(&self) -> Option<&ManagedWindow> {
+        return self.windows.iter().find(|wrapped| wrapped.focused);
+    }
+
+    fn get_focused_mut(&mut self) -> Option<&mut ManagedWindow> {
+        return self.windows.iter_mut().find(|wrapped| wrapped.focused);
+    }
+
+    fn resize(&mut self, direction: TiledDirection, amount: i16, area: Geometry) -> bool {
+        // Only the split between the master and the stack can be moved
+        if self.kind != LayoutKind::MasterStack || direction != TiledDirection::Horizontal || self.windows.len() < 2 || area.width == 0 {
+            return false;
+        }
+
+        let focused = match self.focused_index() {
+            Some(index) => index,
+            None => return false
+        };
+
+        let amount = amount as f32 / area.width as f32;
+        let ratio = if focused == 0 { self.master_ratio + amount } else { self.master_ratio - amount };
+
+        // Keep both sides at least as big as a title bar
+        let min = MIN_TILE_SIZE as f32 / area.width as f32;
+        if min * 2.0 > 1.0 {
+            return false;
+        }
+
+        self.master_ratio = ratio.max(min).min(1.0 - min);
+        self.mark_dirty();
+        return true;
+    }
+
+    fn resizes_backwards(&self, wid: u32, direction: TiledDirection) -> Option<bool> {
+        if self.kind != LayoutKind::MasterStack || direction != TiledDirection::Horizontal || self.windows.len() < 2 {
+            return None;
+        }
+
+        // The stack grows by moving the split, which is its left edge
+        return self.windows.iter().position(|wrapped| wrapped.window.wid == wid).map(|index| index != 0);
+    }
+
+    fn move_focused(&mut self, _direction: Direction, neighbour: Option<u32>) -> bool {
+        // The order is all there is, so moving is swapping with whatever is in that direction
+        let focused = match self.focused_index() {
+            Some(index) => index,
+            None => return false
+        };
+        let neighbour = match neighbour.and_then(|wid| self.windows.iter().position(|wrapped| wrapped.window.wid == wid)) {
+            Some(index) => index,
+            None => return false
+        };
+
+        self.windows.swap(focused, neighbour);
+        self.mark_dirty();
+        return true;
+    }
+
+    fn layout(&self, settings: &Settings, area: Geometry) -> Vec<TileAssignment> {
+        // Monocle only shows the focused window (or the first one if nothing is focused)
+        let shown = match self.focused_index() {
+            Some(index) => index,
+            None => 0
+        };
+
+        let mut assignments = Vec::with_capacity(self.windows.len());
+        for (i, (wrapped, window_area)) in self.windows.iter().zip(self.areas(area)).enumerate() {
+            if self.kind == LayoutKind::Monocle && i != shown {
+                assignments.push(TileAssignment::hidden(wrapped.window.wid));
+            } else {
+                assignments.push(TileAssignment::new(settings, wrapped.window.wid, window_area));
+            }
+        }
+
+        return assignments;
+    }
+
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    fn is_dirty(&self) -> bool {
+        return self.dirty;
+    }
+
+    fn mark_clean(&mut self) {
+        self.dirty = false;
+    }
+}
+
+/** Side by side columns of equal width. */
+fn columns(count: usize, area: Geometry) -> Vec<Geometry> {
+    let mut areas = Vec::with_capacity(count);
+    let mut x = area.x;
+
+    for width in split_even(area.width, count) {
+        areas.push(Geometry { x, y: area.y, width, height: area.height });
+        x += width as i16;
+    }
+
+    return areas;
+}
+
+/** The first window on the left, and the rest stacked on top of each other on the right. */
+fn master_stack(count: usize, area: Geometry, ratio: f32) -> Vec<Geometry> {
+    if count <= 1 {
+        return vec![area; count];
+    }
+
+    let master_width = (area.width as f32 * ratio) as u16;
+    let mut areas = Vec::with_capacity(count);
+    areas.push(Geometry { x: area.x, y: area.y, width: master_width, height: area.height });
+
+    let mut y = area.y;
+    for height in split_even(area.height, count - 1) {
+        areas.push(Geometry { x: area.x + master_width as i16, y, width: area.width - master_width, height });
+        y += height as i16;
+    }
+
+    return areas;
+}
+
+/** Rows of (almost) equal columns. The last row may have fewer, wider windows. */
+fn grid(count: usize, area: Geometry) -> Vec<Geometry> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let cols = (count as f32).sqrt().ceil() as usize;
+    let rows = (count + cols - 1) / cols;
+    let mut areas = Vec::with_capacity(count);
+
+    let mut y = area.y;
+    for (row, height) in split_even(area.height, rows).into_iter().enumerate() {
+        let in_row = if row == rows - 1 { count - row * cols } else { cols };
+
+        let mut x = area.x;
+        for width in split_even(area.width, in_row) {
+            areas.push(Geometry { x, y, width, height });
+            x += width as i16;
+        }
+
+        y += height as i16;
+    }
+
+    return areas;
+}
+
+/** Each window takes half of the space left over, alternating between splitting the width and the height. */
+fn spiral(count: usize, area: Geometry) -> Vec<Geometry> {
+    let mut areas = Vec::with_capacity(count);
+    let mut rest = area;
+
+    for i in 0..count {
+        if i == count - 1 {
+            areas.push(rest);
+        } else if i % 2 == 0 {
+            let width = rest.width / 2;
+            areas.push(Geometry { x: rest.x, y: rest.y, width, height: rest.height });
+            rest = Geometry { x: rest.x + width as i16, y: rest.y, width: rest.width - width, height: rest.height };
+        } else {
+            let height = rest.height / 2;
+            areas.push(Geometry { x: rest.x, y: rest.y, width: rest.width, height });
+            rest = Geometry { x: rest.x, y: rest.y + height as i16, width: rest.width, height: rest.height - height };
+        }
+    }
+
+    return areas;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(x: i16, y: i16, width: u16, height: u16) -> Geometry {
+        Geometry { x, y, width, height }
+    }
+
+    #[test]
+    fn columns_split_the_width() {
+        assert_eq!(columns(3, area(10, 5, 100, 50)), vec![area(10, 5, 33, 50), area(43, 5, 33, 50), area(76, 5, 34, 50)]);
+        assert_eq!(columns(0, area(0, 0, 100, 50)), vec![]);
+    }
+
+    #[test]
+    fn master_stack_puts_the_rest_on_the_right() {
+        assert_eq!(master_stack(3, area(10, 0, 200, 100), 0.5), vec![
+            area(10, 0, 100, 100),
+            area(110, 0, 100, 50),
+            area(110, 50, 100, 50)
+        ]);
+        assert_eq!(master_stack(2, area(0, 0, 200, 100), 0.25), vec![area(0, 0, 50, 100), area(50, 0, 150, 100)]);
+    }
+
+    #[test]
+    fn master_stack_alone_fills_the_area() {
+        assert_eq!(master_stack(1, area(10, 5, 200, 100), 0.5), vec![area(10, 5, 200, 100)]);
+        assert_eq!(master_stack(0, area(10, 5, 200, 100), 0.5), vec![]);
+    }
+
+    #[test]
+    fn grid_widens_the_last_row() {
+        assert_eq!(grid(5, area(0, 0, 300, 200)), vec![
+            area(0, 0, 100, 100),
+            area(100, 0, 100, 100),
+            area(200, 0, 100, 100),
+            area(0, 100, 150, 100),
+            area(150, 100, 150, 100)
+        ]);
+        assert_eq!(grid(4, area(0, 0, 200, 200)).len(), 4);
+    }
+
+    #[test]
+    fn spiral_halves_what_is_left() {
+        assert_eq!(spiral(3, area(0, 0, 200, 100)), vec![
+            area(0, 0, 100, 100),
+            area(100, 0, 100, 50),
+            area(100, 50, 100, 50)
+        ]);
+        assert_eq!(spiral(1, area(5, 5, 200, 100)), vec![area(5, 5, 200, 100)]);
+    }
+
+    #[test]
+    fn monocle_only_shows_the_focused_window() {
+        let mut layout = FlatLayout::new(LayoutKind::Monocle);
+        for wid in 1..4 {
+            layout.add(ManagedWindow::detached(wid), TiledDirection::Horizontal);
+        }
+        layout.set_focused(2);
+
+        let assignments = layout.layout(&Settings::default(), area(0, 0, 200, 100));
+        let visible: Vec<u32> = assignments.iter().filter(|assignment| assignment.visible).map(|assignment| assignment.wid).collect();
+        assert_eq!(visible, vec![2]);
+        assert_eq!(assignments.iter().find(|assignment| assignment.wid == 2).unwrap().wrapper, area(0, 0, 200, 100));
+    }
+
+    #[test]
+    fn master_stack_resize_moves_the_split() {
+        let mut layout = FlatLayout::new(LayoutKind::MasterStack);
+        layout.add(ManagedWindow::detached(1), TiledDirection::Horizontal);
+        layout.add(ManagedWindow::detached(2), TiledDirection::Horizontal);
+        layout.set_focused(1);
+
+        assert!(layout.resize(TiledDirection::Horizontal, 50, area(0, 0, 200, 100)));
+        assert_eq!(layout.areas(area(0, 0, 200, 100))[0], area(0, 0, 150, 100));
+        assert!(!layout.resize(TiledDirection::Vertical, 50, area(0, 0, 200, 100)));
+    }
+}
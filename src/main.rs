@@ -2,7 +2,11 @@ extern crate xrb;
 
 use xrb::XClient;
 
+mod ewmh;
+mod keys;
+mod layout;
 mod manager;
+mod monitor;
 mod settings;
 mod tiling;
 
@@ -3,20 +3,39 @@ extern crate xrb;
 use xrb::XClient;
 use xrb::models::*;
 
+use ewmh::{client_message_value, create_check_window, get_property32, send_client_message, set_property32, set_utf8_property, wm_state_action, Atoms, SizeHints, Strut};
+use keys::{find_action, Action, Keymap};
+use layout::{Layout, LayoutKind};
+use monitor::{has_randr, query_monitors, select_screen_change, Monitor};
 use settings::Settings;
-use tiling::{Tiled, TiledDirection, TiledChild};
+use tiling::{find_neighbour, Direction, Geometry, TileAssignment, Tiled, TiledDirection, TiledChild, TiledMode, MIN_TILE_SIZE, TITLE_HEIGHT};
 
+use std::cmp::{max, min};
 use std::process::Command;
 
 pub struct WindowManager {
     client: XClient,
     workspaces: Vec<Workspace>,
-    current_workspace: usize,
+    current_workspace: usize, // The workspace shown on the current monitor
+    monitors: Vec<Monitor>,
+    current_monitor: usize, // The monitor with the focus
+    docks: Vec<(Window, Strut)>, // Panels and bars, left unparented, and the space they reserve
+    randr: bool, // If the server has RandR, so monitors can be queried
     gc: GraphicsContext,
     settings: Settings,
     tile_direction: TiledDirection,
+    resize_mode: bool, // If true, key presses resize the focused window until Escape or Return is pressed
     display: String, // Passed to spawned processes as DISPLAY
-    ATOM__NET_WM_NAME: u32
+    root: u32,
+    client_list: Vec<u32>, // Managed windows, oldest first, for _NET_CLIENT_LIST
+    client_stacking: Option<Vec<u32>>, // Last published _NET_CLIENT_LIST_STACKING, so unchanged lists aren't published again
+    pending_close: Vec<(u32, u32)>, // Windows sent WM_DELETE_WINDOW that haven't closed yet, and when it was sent
+    input_focus: u32, // Window last given the X input focus, or the root if none
+    last_time: u32, // Timestamp of the latest event that had one, for focus and close requests
+    drag: Option<Drag>, // Mouse drag in progress
+    last_click: Option<(u32, u32)>, // Window and time of the last title bar click, for double clicks
+    keymap: Keymap,
+    atoms: Atoms
 }
 impl WindowManager {
 
@@ -25,17 +44,9 @@ impl WindowManager {
      * client should already be authenticated. This will set the bitmask.
      */
     pub fn new(mut client: XClient, display: String) -> WindowManager {
-        // Create WM name atom
-        let seq = client.intern_atom(&"_NET_WM_NAME", false);
-        let ATOM__NET_WM_NAME = match client.wait_for_response(seq) {
-            ServerResponse::Error(err, _) => panic!("Failed to get _NET_WM_NAME atom"),
-            ServerResponse::Reply(reply, _) => match reply {
-                ServerReply::InternAtom { atom }
-                    => atom,
-                _ => unreachable!()
-            },
-            _ => unreachable!()
-        };
+        // Create atoms
+        let atoms = Atoms::intern(&mut client);
+        let keymap = Keymap::get(&mut client);
 
         // Create the graphics context
         let root = client.info.screens[0].root;
@@ -56,26 +67,59 @@ impl WindowManager {
             Err(err) => panic!("Failed to subscribe to root pane: {:?}", err)
         };
         root.set_multiple(&mut client, vec![
-            WindowValue::EventMask(Event::KeyPress.val() | Event::SubstructureRedirect.val() | Event::FocusChange.val())
+            WindowValue::EventMask(Event::SubstructureRedirect.val() | Event::FocusChange.val())
         ]);
 
+        // Let other clients know an EWMH compliant window manager is running
+        create_check_window(&mut client, &atoms, root_id);
+
+        // Find the monitors, and follow them being plugged in and out
+        let randr = has_randr(&mut client);
+        if randr {
+            select_screen_change(&mut client, root_id);
+        }
+        let monitors = query_monitors(&mut client, 0, randr);
+
         // Create the manager
-        let workspaces = Vec::with_capacity(client.info.screens.len());
+        let workspaces = Vec::with_capacity(monitors.len());
         let mut manager = WindowManager {
             client,
             workspaces: workspaces,
             current_workspace: 0,
+            monitors: Vec::with_capacity(monitors.len()),
+            current_monitor: 0,
+            docks: Vec::new(),
+            randr,
             gc,
             settings: Settings::default(),
             tile_direction: TiledDirection::Vertical,
+            resize_mode: false,
             display,
-            ATOM__NET_WM_NAME
+            root: root_id,
+            client_list: Vec::new(),
+            client_stacking: None,
+            pending_close: Vec::new(),
+            input_focus: 0,
+            last_time: 0, // CurrentTime
+            drag: None,
+            last_click: None,
+            keymap,
+            atoms
         };
 
-        // Create initial workspaces
-        for i in 0..manager.client.info.screens.len() {
-            manager.create_workspace(i as u32 + 1, i);
+        // Create initial workspaces, one shown on each monitor
+        for (i, geometry) in monitors.into_iter().enumerate() {
+            manager.monitors.push(Monitor::new(geometry, 0));
+            manager.current_monitor = i;
+            manager.monitors[i].workspace = manager.create_workspace(i as u32 + 1).unwrap();
+            manager.show_monitor(i);
         }
+        manager.focus_monitor(0);
+        manager.update_client_list();
+        manager.grab_keys();
+
+        // Manage windows that were mapped before we started
+        manager.adopt_windows();
 
         manager
     }
@@ -83,18 +127,31 @@ impl WindowManager {
     /**
      * Presumably used during ServerEvent::MapRequest.
      * Reparents the given window and maps it.
+     * Transients, dialogs and fixed size windows float, centred over the window they belong to. Docks aren't wrapped, see add_dock.
      */
     pub fn add_window(&mut self, mut window: Window, parent: Window) {
+        let wid = window.wid;
+        if self.is_dock(wid) {
+            self.add_dock(window);
+            return;
+        }
+
+        let transient_for = get_property32(&mut self.client, wid, DefaultAtom::WmTransientFor.val(), DefaultAtom::Window.val()).first().cloned()
+            .filter(|&owner| owner != 0 && owner != self.root && self.workspaces.iter().any(|workspace| workspace.get_window(owner).is_some())); // Only managed windows can own transients
+        let size_hints = SizeHints::get(&mut self.client, wid);
+        let float = transient_for.is_some() || self.is_floating_type(wid) || size_hints.is_fixed();
+
         {
             let workspace = &mut self.workspaces[self.current_workspace];
 
-            // Create wrapper
+            // Create wrapper where the client asked to be. It goes in the workspace window, which may not be at the root's origin
+            let (x, y) = (window.x - workspace.geometry.x, window.y - workspace.geometry.y);
             let wrapper = Window::create(
                 &mut self.client,
                 workspace.window.wid,//parent.wid,
                 window.depth,
-                window.x,
-                window.y,
+                x,
+                y,
                 window.width,
                 window.height + 20,
                 0,
@@ -103,13 +160,16 @@ impl WindowManager {
                 vec![
                     WindowValue::Colormap(0x0),
                     WindowValue::EventMask(
-                        Event::Button1Motion.val() | Event::Exposure.val() | Event::SubstructureNotify.val() | Event::KeyPress.val() | Event::EnterWindow.val()
+                        Event::Button1Motion.val() | Event::Exposure.val() | Event::SubstructureNotify.val() | Event::EnterWindow.val()
                     )
                 ]
             );
             
+            // Any click focuses the window. The click is grabbed synchronously so it can be passed on to the client afterwards
+            self.client.grab_button(false, wrapper.wid, Event::ButtonPress.val(), GrabMode::Sync, GrabMode::Async, 0, 0, 0, 0x8000); // AnyButton, AnyModifier
+
             // List to some events for the window
-            window.set(&mut self.client, WindowValue::EventMask(Event::PropertyChange.val()));
+            window.set(&mut self.client, WindowValue::EventMask(Event::PropertyChange.val() | Event::FocusChange.val()));
 
             // Put window inside wrapper and map
             window.reparent(&mut self.client, wrapper.wid, 0, 20);
@@ -117,65 +177,197 @@ impl WindowManager {
             wrapper.map(&mut self.client);
 
             // Get the window's name
-            let name = window.get_wm_name_sync(&mut self.client, self.ATOM__NET_WM_NAME);
+            let name = window.get_wm_name_sync(&mut self.client, self.atoms._NET_WM_NAME);
 
-            // Add to list of windows
-            workspace.tiling.add(ManagedWindow {
+            // Add to list of windows, floating where the client asked to be if it is ever floated
+            let title = Geometry { x: 0, y: 0, width: wrapper.width, height: TITLE_HEIGHT };
+            let floating = Geometry { x, y, width: wrapper.width, height: wrapper.height };
+            let placed = Geometry { x, y: y + TITLE_HEIGHT as i16, width: window.width, height: window.height };
+            let mut managed = ManagedWindow {
                 window,
                 wrapper,
                 focused: false,
+                active: false,
+                title,
+                floating,
+                placed,
+                fullscreen: false,
+                maximized: false,
+                urgent: false,
+                demands_attention: false,
+                size_hints,
+                accepts_input: true,
+                takes_focus: false,
                 parent,
                 name
-            }, self.tile_direction);
+            };
+            managed.update_focus_model(&mut self.client, &self.atoms);
+            managed.update_urgency(&mut self.client);
+
+            if float {
+                let over = match transient_for.and_then(|owner| workspace.get_window(owner)) {
+                    Some(owner) => Geometry { x: owner.wrapper.x, y: owner.wrapper.y, width: owner.wrapper.width, height: owner.wrapper.height },
+                    None => workspace.area()
+                };
+
+                managed.floating = managed.floating.centred_in(over);
+                workspace.add_floating(managed);
+            } else {
+                workspace.add(managed, self.tile_direction);
+            }
         }
 
+        self.client_list.push(wid);
+        self.update_client_list();
+        let desktop = self.current_workspace as u32;
+        set_property32(&mut self.client, wid, self.atoms._NET_WM_DESKTOP, DefaultAtom::Cardinal.val(), &[desktop]);
+
         // Re-tile
         self.tile();
+
+        // Adding focused the window
+        self.update_active_window();
+
+        // Clients may ask to start fullscreen
+        if get_property32(&mut self.client, wid, self.atoms._NET_WM_STATE, DefaultAtom::Atom.val()).contains(&self.atoms._NET_WM_STATE_FULLSCREEN) {
+            self.set_fullscreen(wid, true);
+        }
     }
 
     /**
-     * Creates an empty workspace on the given screen.
-     * Returns true if the operation succeeded and false if not.
+     * Manages every window that is already mapped, such as when the window manager is restarted.
+     * Override-redirect windows (menus, tooltips...) and our own workspace windows are left alone.
      */
-    pub fn create_workspace(&mut self, id: u32, screen: usize) -> bool {
-        for workspace in self.workspaces.iter() {
-            if workspace.id == id {
-                return false;
+    pub fn adopt_windows(&mut self) {
+        let seq = self.client.query_tree(self.root);
+        let children = match self.client.wait_for_response(seq) {
+            ServerResponse::Reply(ServerReply::QueryTree { children, .. }, _) => children,
+            ServerResponse::Error(err, _) => {
+                println!("Failed to query existing windows: {:?}", err);
+                return;
+            },
+            _ => return
+        };
+
+        for wid in children {
+            if self.workspaces.iter().any(|workspace| workspace.window.wid == wid) {
+                continue;
+            }
+
+            let seq = self.client.get_window_attributes(wid);
+            let adopt = match self.client.wait_for_response(seq) {
+                ServerResponse::Reply(ServerReply::GetWindowAttributes { map_state, override_redirect, .. }, _)
+                    => map_state == MapState::Viewable && !override_redirect,
+                _ => false // Destroyed while we were looking
+            };
+            if !adopt {
+                continue;
+            }
+
+            let window = match Window::get_sync(&mut self.client, wid) {
+                Ok(window) => window,
+                Err(_) => continue
+            };
+            let parent = Window::get_sync(&mut self.client, self.root).unwrap();
+            self.add_window(window, parent);
+        }
+    }
+
+    /**
+     * Returns the index of the workspace with the given ID, creating it if it doesn't exist.
+     */
+    fn workspace_index(&mut self, id: u32) -> usize {
+        match self.find_workspace(id) {
+            Some(index) => index,
+            None => self.create_workspace(id).unwrap()
+        }
+    }
+
+    /**
+     * Returns true if the given window's _NET_WM_WINDOW_TYPE says it should float (dialogs, menus, splash screens...).
+     */
+    fn is_floating_type(&mut self, wid: u32) -> bool {
+        let types = get_property32(&mut self.client, wid, self.atoms._NET_WM_WINDOW_TYPE, DefaultAtom::Atom.val());
+        let floating = self.atoms.floating_types();
+
+        return types.iter().any(|window_type| floating.contains(window_type));
+    }
+
+    /**
+     * Returns the index of the workspace with the given ID, if it exists.
+     */
+    pub fn find_workspace(&self, id: u32) -> Option<usize> {
+        return self.workspaces.iter().position(|workspace| workspace.id == id);
+    }
+
+    /**
+     * Creates an empty workspace, sized for the current monitor. Doesn't switch to it.
+     * Workspaces are kept sorted by ID, so the indexes of later workspaces shift.
+     * Returns the new workspace's index, or None if a workspace with that ID already exists.
+     */
+    pub fn create_workspace(&mut self, id: u32) -> Option<usize> {
+        if self.find_workspace(id).is_some() {
+            return None;
+        }
+
+        let depth = self.client.info.screens[0].root_depth;
+        let visual = self.client.info.screens[0].root_visual;
+        let geometry = self.monitors[self.current_monitor].geometry;
+        let area = self.monitors[self.current_monitor].area;
+        let index = self.workspaces.iter().position(|workspace| workspace.id > id).unwrap_or(self.workspaces.len());
+        let window = Window::create(
+            &mut self.client,
+            self.root,
+            depth,
+            geometry.x,
+            geometry.y,
+            geometry.width,
+            geometry.height,
+            0,
+            WindowInputType::CopyFromParent,
+            visual,
+            vec![
+                // Entering a workspace window means the pointer moved onto an empty part of its monitor
+                WindowValue::EventMask(Event::Exposure.val() | Event::EnterWindow.val())
+            ]
+        );
+
+        // Mod + left drag moves floating windows or swaps tiles, Mod + right drag resizes either
+        let drag_events = Event::ButtonPress.val() | Event::ButtonRelease.val() | Event::PointerMotion.val();
+        for button in [1, 3].iter() {
+            for locked in lock_masks().iter() {
+                self.client.grab_button(false, window.wid, drag_events, GrabMode::Async, GrabMode::Async, 0, 0, *button, self.settings.mod_key.val() | locked);
             }
         }
 
-        let root = self.client.info.screens[screen].root;
-        let depth = self.client.info.screens[screen].root_depth;
-        let width = self.client.info.screens[screen].width_in_pixels;
-        let height = self.client.info.screens[screen].height_in_pixels;
-        let visual = self.client.info.screens[screen].root_visual;
-        self.workspaces.push(Workspace {
+        self.workspaces.insert(index, Workspace {
             id,
-            window: Window::create(
-                &mut self.client,
-                root,
-                depth,
-                0,
-                0,
-                width,
-                height,
-                0,
-                WindowInputType::CopyFromParent,
-                visual,
-                vec![
-                    WindowValue::EventMask(Event::Exposure.val())
-                ]
-            ),
-            tiling: Tiled::new_0(self.tile_direction)
+            window,
+            geometry,
+            area: Geometry { x: area.x - geometry.x, y: area.y - geometry.y, width: area.width, height: area.height },
+            layout: LayoutKind::Tiled.create(self.tile_direction),
+            floating: Vec::new(),
+            floating_dirty: false
         });
 
-        let new_index = self.workspaces.len() - 1;
-        self.set_workspace(new_index);
-        return true;
+        // Keep pointing at the same workspaces
+        if self.workspaces.len() > 1 {
+            if index <= self.current_workspace {
+                self.current_workspace += 1;
+            }
+            for monitor in self.monitors.iter_mut().filter(|monitor| index <= monitor.workspace) {
+                monitor.workspace += 1;
+            }
+        }
+
+        self.update_desktops();
+        return Some(index);
     }
 
     /**
      * Switches to the given workspace (unmaps old workspace, maps others)
+     * A workspace that is already shown on another monitor is swapped with the current one,
+     * or focused there if Settings::swap_visible_workspaces is off.
      * Returns true if the operation succeeded, and false if not.
      */
     pub fn set_workspace(&mut self, workspace: usize) -> bool {
@@ -184,18 +376,216 @@ impl WindowManager {
             return false;
         }
 
+        let monitor = self.current_monitor;
+        match self.monitor_of(workspace) {
+            Some(other) if other != monitor && self.settings.swap_visible_workspaces => {
+                // Each monitor shows the other's workspace
+                self.monitors[other].workspace = self.current_workspace;
+                self.monitors[monitor].workspace = workspace;
+                self.show_monitor(other);
+                self.show_monitor(monitor);
+                self.tile();
+                return self.focus_monitor(monitor);
+            },
+            Some(other) => return self.focus_monitor(other),
+            None => ()
+        };
+
         // Swap
         self.workspaces[self.current_workspace].window.unmap(&mut self.client);
-        self.current_workspace = workspace;
-        self.workspaces[self.current_workspace].window.map(&mut self.client);
+        self.monitors[monitor].workspace = workspace;
+        self.show_monitor(monitor);
+        self.tile();
 
         // Focus mouse
         // TODO: Focus mouse
+        self.focus_monitor(monitor);
 
         // Return true
         return true;
     }
 
+    /**
+     * Returns the index of the monitor the given workspace is shown on, if it is shown.
+     */
+    pub fn monitor_of(&self, workspace: usize) -> Option<usize> {
+        return self.monitors.iter().position(|monitor| monitor.workspace == workspace);
+    }
+
+    /**
+     * Returns the index of the monitor the given number of places after the current one, wrapping around. Negative offsets go backwards.
+     */
+    pub fn monitor_after(&self, offset: i32) -> usize {
+        let count = self.monitors.len() as i32;
+        return ((self.current_monitor as i32 + offset) % count + count) as usize % count as usize;
+    }
+
+    /**
+     * Moves the focused window to the workspace shown on the monitor the given number of places after the current one.
+     * The focus stays on the current monitor.
+     * Returns true if a window was moved.
+     */
+    pub fn move_focused_to_monitor(&mut self, offset: i32) -> bool {
+        let monitor = self.monitor_after(offset);
+        if monitor == self.current_monitor {
+            return false;
+        }
+
+        let target = self.monitors[monitor].workspace;
+        return self.move_focused_to_workspace(target);
+    }
+
+    /**
+     * Makes the given monitor (and the workspace shown on it) the one with the focus.
+     * Returns false if there is no such monitor.
+     */
+    pub fn focus_monitor(&mut self, monitor: usize) -> bool {
+        if monitor >= self.monitors.len() {
+            return false;
+        }
+
+        self.current_monitor = monitor;
+        self.current_workspace = self.monitors[monitor].workspace;
+        self.update_active_window();
+        self.update_current_desktop();
+        return true;
+    }
+
+    /**
+     * Moves and resizes the given monitor's workspace to cover the monitor, maps it and publishes the new work area.
+     */
+    pub fn show_monitor(&mut self, monitor: usize) {
+        let (geometry, area) = (self.monitors[monitor].geometry, self.monitors[monitor].area);
+        self.workspaces[self.monitors[monitor].workspace].show(&mut self.client, geometry, area);
+        self.update_workarea();
+    }
+
+    /**
+     * Re-reads the monitors after RandR said they changed.
+     * Unplugged monitors' workspaces are hidden, new monitors show a hidden workspace (or a new one), and everything is re-tiled.
+     */
+    pub fn update_monitors(&mut self) {
+        let geometries = query_monitors(&mut self.client, 0, self.randr);
+
+        while self.monitors.len() > geometries.len() {
+            let monitor = self.monitors.pop().unwrap();
+            self.workspaces[monitor.workspace].window.unmap(&mut self.client);
+        }
+
+        for (i, geometry) in geometries.into_iter().enumerate() {
+            if i < self.monitors.len() {
+                self.monitors[i].geometry = geometry;
+                continue;
+            }
+
+            let hidden = (0..self.workspaces.len()).find(|&index| self.monitor_of(index).is_none());
+            self.monitors.push(Monitor::new(geometry, 0));
+            self.monitors[i].workspace = match hidden {
+                Some(index) => index,
+                None => {
+                    let id = self.workspaces.iter().map(|workspace| workspace.id).max().unwrap_or(0) + 1;
+                    self.create_workspace(id).unwrap()
+                }
+            };
+        }
+
+        self.update_work_areas();
+
+        let current = min(self.current_monitor, self.monitors.len() - 1);
+        self.focus_monitor(current);
+    }
+
+    /**
+     * Shrinks every monitor's area by the space the docks reserve on it, moves the workspaces to fit and re-tiles.
+     */
+    pub fn update_work_areas(&mut self) {
+        let struts: Vec<Strut> = self.docks.iter().map(|&(_, strut)| strut).collect();
+
+        // Struts are measured from the edges of the root window, which spans every monitor
+        let screen_width = self.monitors.iter().map(|monitor| monitor.geometry.x as i32 + monitor.geometry.width as i32).max().unwrap_or(0);
+        let screen_height = self.monitors.iter().map(|monitor| monitor.geometry.y as i32 + monitor.geometry.height as i32).max().unwrap_or(0);
+        for monitor in self.monitors.iter_mut() {
+            monitor.reserve(&struts, screen_width as u32, screen_height as u32);
+        }
+
+        for monitor in 0..self.monitors.len() {
+            self.show_monitor(monitor);
+        }
+        self.tile();
+    }
+
+    /**
+     * Publishes every workspace's area in _NET_WORKAREA on the root window, as x, y, width and height.
+     * Hidden workspaces keep the area of the monitor they were last shown on.
+     */
+    pub fn update_workarea(&mut self) {
+        let mut values = Vec::with_capacity(self.workspaces.len() * 4);
+        for workspace in self.workspaces.iter() {
+            let (geometry, area) = (workspace.geometry, workspace.area);
+            values.extend_from_slice(&[(geometry.x + area.x) as u32, (geometry.y + area.y) as u32, area.width as u32, area.height as u32]);
+        }
+
+        set_property32(&mut self.client, self.root, self.atoms._NET_WORKAREA, DefaultAtom::Cardinal.val(), &values);
+    }
+
+    /**
+     * Returns true if the given window's _NET_WM_WINDOW_TYPE says it is a dock (a panel or bar).
+     */
+    fn is_dock(&mut self, wid: u32) -> bool {
+        let types = get_property32(&mut self.client, wid, self.atoms._NET_WM_WINDOW_TYPE, DefaultAtom::Atom.val());
+        return types.contains(&self.atoms._NET_WM_WINDOW_TYPE_DOCK);
+    }
+
+    /**
+     * Maps a dock where it asked to be, without wrapping it, and keeps its strut free of windows.
+     */
+    pub fn add_dock(&mut self, mut window: Window) {
+        let wid = window.wid;
+        if self.docks.iter().any(|&(ref dock, _)| dock.wid == wid) {
+            return;
+        }
+
+        // Follow its strut, and find out when it goes away
+        window.set(&mut self.client, WindowValue::EventMask(Event::PropertyChange.val() | Event::StructureNotify.val()));
+        window.map(&mut self.client);
+
+        let strut = Strut::get(&mut self.client, &self.atoms, wid);
+        self.docks.push((window, strut));
+        self.update_work_areas();
+    }
+
+    /**
+     * Re-reads a dock's strut after it changed, and re-tiles.
+     */
+    pub fn update_dock_strut(&mut self, wid: u32) {
+        let strut = Strut::get(&mut self.client, &self.atoms, wid);
+        match self.docks.iter_mut().find(|&&mut (ref dock, _)| dock.wid == wid) {
+            Some(dock) => {
+                if dock.1 == strut {
+                    return;
+                }
+                dock.1 = strut;
+            },
+            None => return
+        };
+
+        self.update_work_areas();
+    }
+
+    /**
+     * Forgets a dock that was unmapped or destroyed, giving its space back to the workspaces.
+     * Returns true if the window was a dock.
+     */
+    pub fn remove_dock(&mut self, wid: u32) -> bool {
+        if !self.docks.iter().any(|&(ref dock, _)| dock.wid == wid) {
+            return false;
+        }
+
+        self.docks.retain(|&(ref dock, _)| dock.wid != wid);
+        self.update_work_areas();
+        return true;
+    }
+
     /**
      * Tiles the registered windows.
      */
@@ -204,8 +594,157 @@ impl WindowManager {
             //println!("WORKSPACE");
             //debug_tiled_print(&mut workspace.tiling, 1);
 
-            workspace.tiling.tile(&mut self.client, &mut self.gc, workspace.window.wid, workspace.window.depth, &self.settings, workspace.window.x, workspace.window.y, workspace.window.width, workspace.window.height, false);
+            workspace.tile(&mut self.client, &self.settings, false);
+        }
+        self.restack_docks();
+
+        // Tiling may have restacked windows
+        self.update_client_list();
+    }
+
+    /**
+     * Keeps the docks above the workspace windows, except for workspaces with a fullscreen window, which go above the docks.
+     */
+    pub fn restack_docks(&mut self) {
+        for &mut (ref mut dock, _) in self.docks.iter_mut() {
+            dock.configure_multiple(&mut self.client, vec![WindowConfigureValue::StackMode(StackMode::Above)]);
+        }
+
+        for monitor in self.monitors.iter() {
+            let workspace = &mut self.workspaces[monitor.workspace];
+            if workspace.has_fullscreen() {
+                workspace.window.configure_multiple(&mut self.client, vec![WindowConfigureValue::StackMode(StackMode::Above)]);
+            }
+        }
+    }
+
+    /**
+     * Grows (or shrinks, if amount is negative) the focused window in the current workspace and re-tiles.
+     */
+    pub fn resize_focused(&mut self, direction: TiledDirection, amount: i16) {
+        let workspace = &mut self.workspaces[self.current_workspace];
+        let area = workspace.area();
+
+        if workspace.layout.resize(direction, amount, area) {
+            self.tile();
+        }
+    }
+
+    /**
+     * Enters or leaves resize mode. The keyboard is grabbed while in it, so resize bindings work without modifiers.
+     */
+    pub fn set_resize_mode(&mut self, resize_mode: bool) {
+        if resize_mode == self.resize_mode {
+            return;
+        }
+
+        self.resize_mode = resize_mode;
+        if resize_mode {
+            self.client.grab_keyboard(false, self.root, self.last_time, GrabMode::Async, GrabMode::Async);
+        } else {
+            self.client.ungrab_keyboard(self.last_time);
+        }
+    }
+
+    /**
+     * Grabs every key binding on the root window, so they work whichever window has the focus.
+     * Each binding is grabbed with and without Caps Lock and Num Lock.
+     */
+    pub fn grab_keys(&mut self) {
+        self.client.ungrab_key(0, self.root, 0x8000); // AnyKey, AnyModifier
+
+        let locks = lock_masks();
+        for binding in self.settings.key_bindings.iter() {
+            let mask = binding.modifier_mask();
+            for keycode in self.keymap.keycodes(binding.keysym) {
+                for locked in locks.iter() {
+                    self.client.grab_key(false, self.root, mask | locked, keycode, GrabMode::Async, GrabMode::Async);
+                }
+            }
+        }
+    }
+
+    /**
+     * Does what a key binding asks for.
+     */
+    pub fn run_action(&mut self, action: Action) {
+        match action {
+            Action::Spawn(command) => {
+                let mut parts = command.split_whitespace();
+                let program = match parts.next() {
+                    Some(program) => program,
+                    None => return
+                };
+
+                match Command::new(program).args(parts).env("DISPLAY", self.display.clone()).spawn() {
+                    Ok(_) => (),
+                    Err(err) => println!("Failed to start process! {}", err)
+                };
+            },
+            Action::Close => self.close_focused(),
+            Action::Workspace(id) => {
+                let index = self.workspace_index(id);
+                self.set_workspace(index);
+            },
+            Action::MoveToWorkspace(id) => {
+                let index = self.workspace_index(id);
+                self.move_focused_to_workspace(index);
+            },
+            Action::FocusMonitor(offset) => {
+                let monitor = self.monitor_after(offset);
+                self.focus_monitor(monitor);
+            },
+            Action::MoveToMonitor(offset) => { self.move_focused_to_monitor(offset); },
+            Action::Focus(direction) => { self.focus_direction(direction); },
+            Action::Move(direction) => self.move_focused(direction),
+            Action::Resize(direction) => {
+                let step = self.settings.resize_step;
+                self.resize_focused(direction.axis(), if direction.is_forward() { step } else { -step });
+            },
+            Action::ResizeMode => self.set_resize_mode(true),
+            Action::ExitResizeMode => self.set_resize_mode(false),
+            Action::ContainerMode(mode) => self.set_container_mode(mode),
+            Action::TileDirection(direction) => self.tile_direction = direction,
+            Action::CycleLayout => self.cycle_layout(),
+            Action::ToggleFloating => self.toggle_floating(),
+            Action::ToggleFullscreen => self.toggle_fullscreen(),
+            Action::FocusUrgent => { self.focus_urgent(); }
+        };
+    }
+
+    /**
+     * Sets how the container of the focused window in the current workspace shows its children.
+     */
+    pub fn set_container_mode(&mut self, mode: TiledMode) {
+        if self.workspaces[self.current_workspace].layout.set_mode(mode) {
+            self.tile();
+        }
+    }
+
+    /**
+     * Switches the current workspace to the next layout, keeping its windows and focus.
+     */
+    pub fn cycle_layout(&mut self) {
+        {
+            let workspace = &mut self.workspaces[self.current_workspace];
+            let mut layout = workspace.layout.kind().next().create(self.tile_direction);
+
+            let windows = workspace.layout.take_all();
+            let focused = windows.iter().find(|wrapped| wrapped.focused).map(|wrapped| wrapped.window.wid);
+            for window in windows {
+                layout.add(window, self.tile_direction);
+            }
+
+            // Adding focuses each new window, so put the focus back (or take it away if a floating window has it)
+            match focused {
+                Some(wid) => { layout.set_focused(wid); },
+                None => { layout.set_focused(0); }
+            };
+
+            workspace.layout = layout;
         }
+
+        self.tile();
     }
 
     /**
@@ -213,7 +752,8 @@ impl WindowManager {
      */
     pub fn update_window_name(&mut self, wid: u32, repaint: bool) {
         for workspace in self.workspaces.iter_mut() {
-            let res = workspace.tiling.get_window_mut(wid);
+            let (workspace_wid, workspace_depth) = (workspace.window.wid, workspace.window.depth);
+            let res = workspace.get_window_mut(wid);
             match res {
                 Some(wrapped) => {
                     if wrapped.window.wid == wid {
@@ -224,7 +764,7 @@ impl WindowManager {
                         };
 
                         if repaint {
-                            wrapped.paint(&mut self.client, &mut self.gc, workspace.window.wid, workspace.window.depth, &self.settings);
+                            wrapped.paint(&mut self.client, &mut self.gc, workspace_wid, workspace_depth, &self.settings);
                         }
 
                         return;
@@ -236,41 +776,250 @@ impl WindowManager {
     }
 
     /**
-     * Paints the wrapper for a managed window
+     * Handles a client asking to move, resize or restack itself.
+     * Tiled windows keep their tile and are told where they really are, floating and unmanaged windows get what they asked for.
+     * value_mask says which of the values were requested.
      */
-    pub fn paint_window(&mut self, wid: u32) {
-        for workspace in self.workspaces.iter_mut() {
-            if workspace.window.wid == wid {
-                workspace.paint_background(&mut self.client, &mut self.gc, &self.settings);
+    pub fn configure_request(&mut self, wid: u32, x: i16, y: i16, width: u16, height: u16, border_width: u16, sibling: u32, stack_mode: StackMode, value_mask: u16) {
+        match self.workspaces.iter().position(|workspace| workspace.is_floating(wid)) {
+            Some(index) => {
+                {
+                    // The wrapper is moved so the client window ends up where it asked, below the title bar
+                    let workspace = &mut self.workspaces[index];
+                    let mut geometry = workspace.get_window(wid).unwrap().floating;
+                    if value_mask & 0x01 != 0 {
+                        geometry.x = x - workspace.geometry.x;
+                    }
+                    if value_mask & 0x02 != 0 {
+                        geometry.y = y - workspace.geometry.y - TITLE_HEIGHT as i16;
+                    }
+                    if value_mask & 0x04 != 0 {
+                        geometry.width = width;
+                    }
+                    if value_mask & 0x08 != 0 {
+                        geometry.height = height + TITLE_HEIGHT;
+                    }
+                    workspace.set_floating_geometry(wid, geometry);
+                }
+
+                self.tile();
+
+                let workspace = &self.workspaces[index];
+                workspace.get_window(wid).unwrap().send_configure_notify(&mut self.client, workspace.geometry.x, workspace.geometry.y);
                 return;
-            }
+            },
+            None => ()
+        };
 
-            let res = workspace.tiling.get_window_mut(wid);
-            match res {
+        for workspace in self.workspaces.iter() {
+            match workspace.get_window(wid) {
                 Some(wrapped) => {
-                    if wrapped.wrapper.wid == wid {
-                        wrapped.paint(&mut self.client, &mut self.gc, workspace.window.wid, workspace.window.depth, &self.settings);
-                        return;
-                    }
+                    wrapped.send_configure_notify(&mut self.client, workspace.geometry.x, workspace.geometry.y);
+                    return;
                 },
                 None => ()
             };
         }
+
+        let mut window = match Window::get_sync(&mut self.client, wid) {
+            Ok(window) => window,
+            Err(_) => return // Destroyed since
+        };
+
+        let mut values = Vec::new();
+        if value_mask & 0x01 != 0 {
+            values.push(WindowConfigureValue::X(x));
+        }
+        if value_mask & 0x02 != 0 {
+            values.push(WindowConfigureValue::Y(y));
+        }
+        if value_mask & 0x04 != 0 {
+            values.push(WindowConfigureValue::Width(width));
+        }
+        if value_mask & 0x08 != 0 {
+            values.push(WindowConfigureValue::Height(height));
+        }
+        if value_mask & 0x10 != 0 {
+            values.push(WindowConfigureValue::BorderWidth(border_width));
+        }
+        if value_mask & 0x20 != 0 {
+            values.push(WindowConfigureValue::Sibling(sibling));
+        }
+        if value_mask & 0x40 != 0 {
+            values.push(WindowConfigureValue::StackMode(stack_mode));
+        }
+
+        window.configure_multiple(&mut self.client, values);
     }
 
     /**
-     * Unmaps a managed window
+     * Re-reads a window's WM_NORMAL_HINTS after they changed, and re-tiles.
      */
-    pub fn unmap_window(&mut self, wid: u32) {
+    pub fn update_window_size_hints(&mut self, wid: u32) {
+        let size_hints = SizeHints::get(&mut self.client, wid);
+
         for workspace in self.workspaces.iter_mut() {
-            let res = workspace.tiling.get_window_mut(wid);
-            match res {
+            let changed = match workspace.get_window_mut(wid) {
                 Some(wrapped) => {
-                    if wrapped.wrapper.wid == wid {
-                        wrapped.wrapper.unmap(&mut self.client);
-                    }
+                    wrapped.size_hints = size_hints;
+                    true
                 },
-                None => ()
+                None => false
+            };
+
+            if changed {
+                workspace.mark_dirty();
+                break;
+            }
+        }
+
+        self.tile();
+    }
+
+    /**
+     * Re-reads a window's urgency hint after its WM_HINTS changed, and repaints it.
+     */
+    pub fn update_window_urgency(&mut self, wid: u32) {
+        let mut wrapper = None;
+
+        for workspace in self.workspaces.iter_mut() {
+            match workspace.get_window_mut(wid) {
+                Some(wrapped) => {
+                    wrapped.update_urgency(&mut self.client);
+                    wrapper = Some(wrapped.wrapper.wid);
+                    break;
+                },
+                None => ()
+            };
+        }
+
+        match wrapper {
+            Some(wrapper) => {
+                self.paint_window(wrapper);
+                self.update_desktop_names();
+            },
+            None => ()
+        };
+    }
+
+    /**
+     * Sets or clears _NET_WM_STATE_DEMANDS_ATTENTION on the given window, publishes it and repaints the window.
+     */
+    pub fn set_demands_attention(&mut self, wid: u32, demands_attention: bool) {
+        let mut wrapper = None;
+
+        for workspace in self.workspaces.iter_mut() {
+            match workspace.get_window_mut(wid) {
+                Some(wrapped) => {
+                    wrapped.demands_attention = demands_attention;
+                    wrapper = Some(wrapped.wrapper.wid);
+                    break;
+                },
+                None => ()
+            };
+        }
+
+        match wrapper {
+            Some(wrapper) => {
+                self.update_wm_state(wid);
+                self.paint_window(wrapper);
+                self.update_desktop_names();
+            },
+            None => ()
+        };
+    }
+
+    /**
+     * Switches to the workspace of the next urgent window and focuses it.
+     * Workspaces after the current one are looked through first, wrapping around.
+     * Returns true if an urgent window was found.
+     */
+    pub fn focus_urgent(&mut self) -> bool {
+        let count = self.workspaces.len();
+        let target = (1..count + 1)
+            .map(|offset| (self.current_workspace + offset) % count)
+            .filter_map(|index| self.workspaces[index].urgent_window().map(|wid| (index, wid)))
+            .next();
+
+        match target {
+            Some((index, wid)) => {
+                self.set_workspace(index);
+                self.focus_window(wid);
+                return true;
+            },
+            None => return false
+        };
+    }
+
+    /**
+     * Switches to the given window's workspace and focuses it, as asked by a _NET_ACTIVE_WINDOW message from a pager or tool.
+     */
+    pub fn activate_window(&mut self, wid: u32) {
+        let index = match self.workspaces.iter().position(|workspace| workspace.get_window(wid).is_some()) {
+            Some(index) => index,
+            None => return
+        };
+
+        self.set_workspace(index);
+        self.focus_window(wid);
+    }
+
+    /**
+     * Re-reads a window's focus model after its WM_HINTS or WM_PROTOCOLS changed.
+     */
+    pub fn update_window_focus_model(&mut self, wid: u32) {
+        for workspace in self.workspaces.iter_mut() {
+            match workspace.get_window_mut(wid) {
+                Some(wrapped) => {
+                    wrapped.update_focus_model(&mut self.client, &self.atoms);
+                    return;
+                },
+                None => ()
+            };
+        }
+    }
+
+    /**
+     * Paints the wrapper for a managed window
+     */
+    pub fn paint_window(&mut self, wid: u32) {
+        for workspace in self.workspaces.iter_mut() {
+            if workspace.window.wid == wid {
+                workspace.paint_background(&mut self.client, &mut self.gc, &self.settings);
+                return;
+            }
+
+            let (workspace_wid, workspace_depth) = (workspace.window.wid, workspace.window.depth);
+            let res = workspace.get_window_mut(wid);
+            match res {
+                Some(wrapped) => {
+                    if wrapped.wrapper.wid == wid {
+                        wrapped.paint(&mut self.client, &mut self.gc, workspace_wid, workspace_depth, &self.settings);
+                        return;
+                    }
+                },
+                None => ()
+            };
+        }
+    }
+
+    /**
+     * Unmaps a managed window
+     */
+    pub fn unmap_window(&mut self, wid: u32) {
+        if self.remove_dock(wid) {
+            return;
+        }
+
+        for workspace in self.workspaces.iter_mut() {
+            let res = workspace.get_window_mut(wid);
+            match res {
+                Some(wrapped) => {
+                    if wrapped.wrapper.wid == wid {
+                        wrapped.wrapper.unmap(&mut self.client);
+                    }
+                },
+                None => ()
             }
         }
     }
@@ -279,11 +1028,15 @@ impl WindowManager {
      * Destroys a managed window
      */
     pub fn destroy_window(&mut self, wid: u32) {
+        if self.remove_dock(wid) {
+            return;
+        }
+
         for workspace in self.workspaces.iter_mut() {
             let mut matched = false;
 
             {
-                let res = workspace.tiling.get_window_mut(wid);
+                let res = workspace.get_window_mut(wid);
                 match res {
                     Some(wrapped) => {
                         wrapped.wrapper.destroy(&mut self.client);
@@ -294,53 +1047,600 @@ impl WindowManager {
             }
 
             if matched {
-                workspace.tiling.remove(wid);
+                workspace.remove(wid);
             }
         }
+
+        if self.client_list.contains(&wid) {
+            self.client_list.retain(|&managed| managed != wid);
+            self.update_client_list();
+        }
+
+        self.pending_close.retain(|&(pending, _)| pending != wid);
+        self.update_desktop_names(); // It may have been the workspace's urgent window
+
+        // Removing the window may have focused another one
+        self.update_active_window();
     }
 
     /**
-     * Makes the window with the given ID focused, unfocusing any focused windows.
+     * Asks the focused window to close with WM_DELETE_WINDOW if it supports it, and kills it otherwise.
+     * Closing it again within close_timeout of asking kills it. After that it is asked again, since it may have
+     * cancelled (an editor asking to save changes, for example).
+     */
+    pub fn close_focused(&mut self) {
+        let wid = match self.get_focused() {
+            Some(wrapped) => wrapped.window.wid,
+            None => return
+        };
+
+        let time = self.last_time;
+        let asked = self.pending_close.iter().find(|&&(pending, _)| pending == wid).map(|&(_, asked)| asked);
+        self.pending_close.retain(|&(pending, _)| pending != wid);
+        match asked {
+            Some(asked) if time.wrapping_sub(asked) <= self.settings.close_timeout => {
+                self.client.kill_client(wid);
+                return;
+            },
+            _ => ()
+        };
+
+        let protocols = get_property32(&mut self.client, wid, self.atoms.WM_PROTOCOLS, DefaultAtom::Atom.val());
+        if protocols.contains(&self.atoms.WM_DELETE_WINDOW) {
+            send_client_message(&mut self.client, wid, self.atoms.WM_PROTOCOLS, &[self.atoms.WM_DELETE_WINDOW, time]);
+            self.pending_close.push((wid, time));
+        } else {
+            self.client.kill_client(wid);
+        }
+    }
+
+    /**
+     * Makes the window with the given ID focused, unfocusing any focused windows in its workspace.
+     * Every workspace remembers its own focused window.
      * Returns true if a window's state was changed.
      */
     pub fn set_focused(&mut self, wid: u32) -> bool {
-        let mut set = false;
+        let index = match self.workspaces.iter().position(|workspace| workspace.get_window(wid).is_some()) {
+            Some(index) => index,
+            None => return false
+        };
+        if !self.workspaces[index].set_focused(wid) {
+            return false;
+        }
 
-        for workspace in self.workspaces.iter_mut() {
-            if workspace.tiling.set_focused(wid) {
-                set = true;
+        // Focusing a window on another monitor focuses that monitor
+        match self.monitor_of(index) {
+            Some(monitor) if monitor != self.current_monitor => { self.focus_monitor(monitor); },
+            _ => self.update_active_window()
+        };
+
+        return true;
+    }
+
+    /**
+     * Publishes the managed windows in _NET_CLIENT_LIST and _NET_CLIENT_LIST_STACKING on the root window.
+     * Nothing is published if the stacking order is the same as last time, which also means the same windows are managed.
+     */
+    pub fn update_client_list(&mut self) {
+        // Bottom to top: hidden workspaces, then the shown ones
+        let (shown, hidden): (Vec<usize>, Vec<usize>) = (0..self.workspaces.len()).partition(|&index| self.monitor_of(index).is_some());
+        let mut stacking = Vec::with_capacity(self.client_list.len());
+        for index in hidden.into_iter().chain(shown) {
+            stacking.extend(self.workspaces[index].stacking_order(&self.settings));
+        }
+        if self.client_stacking.as_ref() == Some(&stacking) {
+            return;
+        }
+
+        set_property32(&mut self.client, self.root, self.atoms._NET_CLIENT_LIST, DefaultAtom::Window.val(), &self.client_list);
+        set_property32(&mut self.client, self.root, self.atoms._NET_CLIENT_LIST_STACKING, DefaultAtom::Window.val(), &stacking);
+        self.client_stacking = Some(stacking);
+    }
+
+    /**
+     * Publishes the focused window of the current workspace in _NET_ACTIVE_WINDOW on the root window,
+     * and gives it the X input focus.
+     */
+    pub fn update_active_window(&mut self) {
+        let (active, demands_attention) = match self.get_focused() {
+            Some(wrapped) => (wrapped.window.wid, wrapped.demands_attention),
+            None => (0, false) // None
+        };
+
+        set_property32(&mut self.client, self.root, self.atoms._NET_ACTIVE_WINDOW, DefaultAtom::Window.val(), &[active]);
+        self.update_input_focus();
+
+        // The window got the attention it wanted
+        if demands_attention {
+            self.set_demands_attention(active, false);
+        }
+    }
+
+    /**
+     * Gives the X input focus to the focused window of the current workspace (or the root if there is none),
+     * following the window's ICCCM focus model. Does nothing if it already has it.
+     * Windows that take no input at all leave the focus on the root.
+     */
+    pub fn update_input_focus(&mut self) {
+        let (wid, accepts_input, takes_focus) = match self.get_focused() {
+            Some(wrapped) => (wrapped.window.wid, wrapped.accepts_input, wrapped.takes_focus),
+            None => (self.root, true, false)
+        };
+        let (wid, accepts_input) = if accepts_input || takes_focus { (wid, accepts_input) } else { (self.root, true) };
+
+        if wid == self.input_focus {
+            return;
+        }
+        self.input_focus = wid;
+
+        // Passive and locally active clients are given the focus, locally and globally active ones are asked to take it
+        if accepts_input {
+            self.client.set_input_focus(InputFocusRevert::PointerRoot, wid, self.last_time);
+        }
+        if takes_focus {
+            let time = self.last_time;
+            send_client_message(&mut self.client, wid, self.atoms.WM_PROTOCOLS, &[self.atoms.WM_TAKE_FOCUS, time]);
+        }
+    }
+
+    /**
+     * Called when a window got the X input focus.
+     * Focus returning to the root is undone, and managed windows that took the focus themselves become focused.
+     */
+    pub fn focus_in(&mut self, wid: u32) {
+        if wid == self.root {
+            if self.input_focus != self.root {
+                self.input_focus = 0;
+                self.update_input_focus();
             }
+            return;
         }
 
-        return set;
+        let managed = self.workspaces[self.current_workspace].get_window(wid).map(|wrapped| wrapped.window.wid == wid).unwrap_or(false);
+        if managed && wid != self.input_focus {
+            self.input_focus = wid;
+            if self.set_focused(wid) {
+                self.tile();
+            }
+        }
     }
 
     /**
-     * Returns the currently focused window as a reference
+     * Publishes the workspaces as desktops (_NET_NUMBER_OF_DESKTOPS, _NET_DESKTOP_NAMES) on the root window,
+     * along with the current desktop and every managed window's _NET_WM_DESKTOP.
      */
-    pub fn get_focused(&self) -> Option<&ManagedWindow> {
+    pub fn update_desktops(&mut self) {
+        let count = self.workspaces.len() as u32;
+        set_property32(&mut self.client, self.root, self.atoms._NET_NUMBER_OF_DESKTOPS, DefaultAtom::Cardinal.val(), &[count]);
+
+        self.update_desktop_names();
+        self.update_workarea();
+
+        // Indexes shift when workspaces are created
+        for (index, workspace) in self.workspaces.iter().enumerate() {
+            for wrapped in workspace.windows() {
+                set_property32(&mut self.client, wrapped.window.wid, self.atoms._NET_WM_DESKTOP, DefaultAtom::Cardinal.val(), &[index as u32]);
+            }
+        }
+
+        self.update_current_desktop();
+    }
+
+    /**
+     * Publishes the workspace names in _NET_DESKTOP_NAMES on the root window.
+     * Workspaces with an urgent window get Settings::urgent_workspace_suffix, so pagers and bars show them as urgent.
+     */
+    pub fn update_desktop_names(&mut self) {
+        // Names are null terminated
+        let mut names = String::new();
         for workspace in self.workspaces.iter() {
-            let focused = workspace.tiling.get_focused();
-            if focused.is_some() {
-                return focused;
+            names.push_str(&workspace.id.to_string());
+            if workspace.urgent_window().is_some() {
+                names.push_str(&self.settings.urgent_workspace_suffix);
             }
+            names.push('\0');
         }
+        set_utf8_property(&mut self.client, &self.atoms, self.root, self.atoms._NET_DESKTOP_NAMES, &names);
+    }
+
+    /**
+     * Publishes the current workspace's index in _NET_CURRENT_DESKTOP on the root window.
+     */
+    pub fn update_current_desktop(&mut self) {
+        let current = self.current_workspace as u32;
+        set_property32(&mut self.client, self.root, self.atoms._NET_CURRENT_DESKTOP, DefaultAtom::Cardinal.val(), &[current]);
+    }
+
+    /**
+     * Focuses the given window: highlights it, gives it the X input focus and re-tiles.
+     */
+    pub fn focus_window(&mut self, wid: u32) {
+        if self.set_focused(wid) {
+            self.tile();
+        }
+    }
+
+    /**
+     * Moves the focus to the nearest window in the given direction on the current workspace.
+     * Returns true if the focus moved.
+     */
+    pub fn focus_direction(&mut self, direction: Direction) -> bool {
+        let target = {
+            let workspace = &self.workspaces[self.current_workspace];
+            let focused = match workspace.layout.get_focused() {
+                Some(wrapped) => wrapped.window.wid,
+                None => return false
+            };
+
+            let assignments = workspace.layout.layout(&self.settings, workspace.area());
+            find_neighbour(&assignments, focused, direction)
+        };
+
+        match target {
+            Some(wid) => {
+                self.focus_window(wid);
+                return true;
+            },
+            None => return false
+        };
+    }
+
+    /**
+     * Moves the focused window on the current workspace in the given direction and re-tiles.
+     */
+    pub fn move_focused(&mut self, direction: Direction) {
+        let moved = {
+            let workspace = &mut self.workspaces[self.current_workspace];
+            let focused = match workspace.layout.get_focused() {
+                Some(wrapped) => wrapped.window.wid,
+                None => return
+            };
+
+            let assignments = workspace.layout.layout(&self.settings, workspace.area());
+            let neighbour = find_neighbour(&assignments, focused, direction);
+            workspace.layout.move_focused(direction, neighbour)
+        };
+
+        if moved {
+            self.tile();
+        }
+    }
+
+    /**
+     * Moves the focused window on the current workspace between the tiles and the floating windows, and re-tiles.
+     */
+    pub fn toggle_floating(&mut self) {
+        let wid = match self.get_focused() {
+            Some(wrapped) => wrapped.window.wid,
+            None => return
+        };
+
+        if self.workspaces[self.current_workspace].toggle_floating(wid, self.tile_direction) {
+            self.tile();
+        }
+    }
+
+    /**
+     * Starts dragging the window with the given wrapper (or client window) on the current workspace.
+     * Button 1 moves floating windows and swaps tiles, button 3 resizes floating windows and moves the split next to tiles.
+     * Returns true if a drag started.
+     */
+    pub fn start_drag(&mut self, wid: u32, button: u8, root_x: i16, root_y: i16) -> bool {
+        let (client_wid, floating, geometry, flip_x, flip_y) = {
+            let workspace = &self.workspaces[self.current_workspace];
+            let wrapped = match workspace.get_window(wid) {
+                Some(wrapped) => wrapped,
+                None => return false
+            };
+
+            if wrapped.fullscreen || wrapped.maximized {
+                return false;
+            }
+
+            // Dragging the edge resize() doesn't move would grow the tile the wrong way, so follow the split it does change
+            let client_wid = wrapped.window.wid;
+            let flip_x = workspace.layout.resizes_backwards(client_wid, TiledDirection::Horizontal).unwrap_or(false);
+            let flip_y = workspace.layout.resizes_backwards(client_wid, TiledDirection::Vertical).unwrap_or(false);
+            (client_wid, workspace.is_floating(wid), wrapped.floating, flip_x, flip_y)
+        };
+
+        let kind = match (button, floating) {
+            (1, true) => DragKind::Move,
+            (3, true) => DragKind::Resize,
+            (1, false) => DragKind::Swap,
+            (3, false) => DragKind::Split { flip_x, flip_y },
+            _ => return false
+        };
+
+        self.focus_window(client_wid);
+        self.drag = Some(Drag {
+            wid: client_wid,
+            kind,
+            pointer: (root_x, root_y),
+            geometry
+        });
+
+        return true;
+    }
+
+    /**
+     * Moves or resizes the dragged window (or the split next to it) to follow the pointer, and re-tiles.
+     */
+    pub fn drag_to(&mut self, root_x: i16, root_y: i16) {
+        let (wid, kind, dx, dy, start) = match self.drag {
+            Some(ref drag) => (drag.wid, drag.kind, root_x - drag.pointer.0, root_y - drag.pointer.1, drag.geometry),
+            None => return
+        };
+
+        match kind {
+            DragKind::Move | DragKind::Resize => {
+                let geometry = if kind == DragKind::Move {
+                    Geometry {
+                        x: start.x + dx,
+                        y: start.y + dy,
+                        ..start
+                    }
+                } else {
+                    Geometry {
+                        width: max(MIN_TILE_SIZE as i32, start.width as i32 + dx as i32) as u16,
+                        height: max(MIN_TILE_SIZE as i32, start.height as i32 + dy as i32) as u16,
+                        ..start
+                    }
+                };
+
+                if self.workspaces[self.current_workspace].set_floating_geometry(wid, geometry) {
+                    self.tile();
+                }
+            },
+            DragKind::Split { flip_x, flip_y } => {
+                let resized = {
+                    let workspace = &mut self.workspaces[self.current_workspace];
+                    let area = workspace.area();
+                    let mut resized = false;
+
+                    if dx != 0 {
+                        resized |= workspace.layout.resize(TiledDirection::Horizontal, if flip_x { -dx } else { dx }, area);
+                    }
+                    if dy != 0 {
+                        resized |= workspace.layout.resize(TiledDirection::Vertical, if flip_y { -dy } else { dy }, area);
+                    }
+
+                    resized
+                };
+
+                // Splits move by the distance since the last motion
+                match self.drag {
+                    Some(ref mut drag) => drag.pointer = (root_x, root_y),
+                    None => ()
+                };
+
+                if resized {
+                    self.tile();
+                }
+            },
+            DragKind::Swap => () // Swapped when dropped
+        };
+    }
+
+    /**
+     * Finishes the mouse drag. A tile dragged with button 1 swaps places with the tile it was dropped on (given by its wrapper).
+     */
+    pub fn end_drag(&mut self, wid: u32) {
+        let drag = match self.drag.take() {
+            Some(drag) => drag,
+            None => return
+        };
+
+        if drag.kind != DragKind::Swap {
+            return;
+        }
+
+        let swapped = {
+            let workspace = &mut self.workspaces[self.current_workspace];
+            let target = match workspace.layout.get_window(wid) {
+                Some(wrapped) => wrapped.window.wid,
+                None => return
+            };
+
+            workspace.layout.swap_windows(drag.wid, target)
+        };
+
+        if swapped {
+            self.tile();
+        }
+    }
+
+    /**
+     * Makes the given window cover its whole workspace (or puts it back where it was), publishes its _NET_WM_STATE and re-tiles.
+     */
+    pub fn set_fullscreen(&mut self, wid: u32, fullscreen: bool) {
+        if !self.workspaces.iter_mut().any(|workspace| workspace.set_fullscreen(wid, fullscreen)) {
+            return;
+        }
+
+        self.update_wm_state(wid);
+        self.tile();
+    }
+
+    /**
+     * Toggles fullscreen for the focused window on the current workspace.
+     */
+    pub fn toggle_fullscreen(&mut self) {
+        let (wid, fullscreen) = match self.get_focused() {
+            Some(wrapped) => (wrapped.window.wid, wrapped.fullscreen),
+            None => return
+        };
+
+        self.set_fullscreen(wid, !fullscreen);
+    }
+
+    /**
+     * Makes the given window fill its workspace like a lone tile (or puts it back where it was), and re-tiles.
+     */
+    pub fn set_maximized(&mut self, wid: u32, maximized: bool) {
+        if self.workspaces.iter_mut().any(|workspace| workspace.set_maximized(wid, maximized)) {
+            self.tile();
+        }
+    }
+
+    /**
+     * Toggles maximize for the focused window on the current workspace.
+     */
+    pub fn toggle_maximized(&mut self) {
+        let (wid, maximized) = match self.get_focused() {
+            Some(wrapped) => (wrapped.window.wid, wrapped.maximized),
+            None => return
+        };
+
+        self.set_maximized(wid, !maximized);
+    }
+
+    /**
+     * Handles a click on the given wrapper (or client window) on the current workspace. x and y are relative to the wrapper.
+     * Any click focuses the window. Button 1 on a title button presses it, and double clicking the rest of the title bar toggles maximize.
+     * Returns true if the click was used up by the title bar, so the client shouldn't get it.
+     */
+    pub fn click(&mut self, wid: u32, button: u8, x: i16, y: i16, time: u32) -> bool {
+        let (client_wid, title, title_button) = match self.workspaces[self.current_workspace].get_window(wid) {
+            Some(wrapped) => (wrapped.window.wid, wrapped.title, wrapped.title_button_at(x, y)),
+            None => return false
+        };
+
+        self.focus_window(client_wid);
+        if button != 1 || !title.contains(x, y) {
+            return false;
+        }
+
+        match title_button {
+            Some(TitleButton::Close) => self.close_focused(),
+            Some(TitleButton::Float) => self.toggle_floating(),
+            Some(TitleButton::Fullscreen) => self.toggle_fullscreen(),
+            None => {
+                let double_click = match self.last_click {
+                    Some((last_wid, last_time)) => last_wid == client_wid && time.wrapping_sub(last_time) <= self.settings.double_click_time,
+                    None => false
+                };
+
+                if double_click {
+                    self.last_click = None;
+                    self.toggle_maximized();
+                } else {
+                    self.last_click = Some((client_wid, time));
+                }
+            }
+        };
+
+        return true;
+    }
+
+    /**
+     * Handles a _NET_WM_STATE ClientMessage for the given window.
+     * Only _NET_WM_STATE_FULLSCREEN and _NET_WM_STATE_DEMANDS_ATTENTION are supported.
+     */
+    pub fn wm_state_message(&mut self, wid: u32, data: &[u8]) {
+        let action = client_message_value(data, 0);
+        let properties = [client_message_value(data, 1), client_message_value(data, 2)];
+
+        let (fullscreen, demands_attention) = match self.workspaces.iter().filter_map(|workspace| workspace.get_window(wid)).next() {
+            Some(wrapped) => (wrapped.fullscreen, wrapped.demands_attention),
+            None => return
+        };
+
+        if properties.contains(&self.atoms._NET_WM_STATE_FULLSCREEN) {
+            self.set_fullscreen(wid, wm_state_action(fullscreen, action));
+        }
+        if properties.contains(&self.atoms._NET_WM_STATE_DEMANDS_ATTENTION) {
+            self.set_demands_attention(wid, wm_state_action(demands_attention, action));
+        }
+    }
+
+    /**
+     * Publishes the given window's _NET_WM_STATE.
+     */
+    pub fn update_wm_state(&mut self, wid: u32) {
+        let state = match self.workspaces.iter().filter_map(|workspace| workspace.get_window(wid)).next() {
+            Some(wrapped) => wrapped.wm_state(&self.atoms),
+            None => return
+        };
+
+        set_property32(&mut self.client, wid, self.atoms._NET_WM_STATE, DefaultAtom::Atom.val(), &state);
+    }
+
+    /**
+     * Returns the currently focused window as a reference
+     */
+    pub fn get_focused(&self) -> Option<&ManagedWindow> {
+        return self.workspaces[self.current_workspace].get_focused();
+    }
+
+    /**
+     * Returns the currently focused window as a mutable reference
+     */
+    pub fn get_focused_mut(&mut self) -> Option<&mut ManagedWindow> {
+        return self.workspaces[self.current_workspace].get_focused_mut();
+    }
+
+    /**
+     * Moves the focused window on the current workspace to the given workspace, where it becomes that workspace's focused window.
+     * The focus stays on the current workspace.
+     * Returns true if a window was moved.
+     */
+    pub fn move_focused_to_workspace(&mut self, target: usize) -> bool {
+        let wid = match self.get_focused() {
+            Some(wrapped) => wrapped.window.wid,
+            None => return false
+        };
 
-        return None;
+        return self.move_window_to_workspace(wid, target);
     }
 
     /**
-     * Returns the currently focused window as a mutable reference
+     * Moves a managed window from whichever workspace it is on to the given workspace, where it becomes that workspace's focused window.
+     * If it was on the current workspace, the focus stays there.
+     * Returns true if the window was moved.
      */
-    pub fn get_focused_mut(&mut self) -> Option<&mut ManagedWindow> {
-        for workspace in self.workspaces.iter_mut() {
-            let focused = workspace.tiling.get_focused_mut();
-            if focused.is_some() {
-                return focused;
+    pub fn move_window_to_workspace(&mut self, wid: u32, target: usize) -> bool {
+        if target >= self.workspaces.len() {
+            return false;
+        }
+
+        let source = match self.workspaces.iter().position(|workspace| workspace.get_window(wid).is_some()) {
+            Some(source) => source,
+            None => return false
+        };
+        if source == target {
+            return false;
+        }
+
+        let floating = self.workspaces[source].is_floating(wid);
+        let mut window = match self.workspaces[source].remove(wid) {
+            Some(window) => window,
+            None => return false
+        };
+
+        {
+            let workspace = &mut self.workspaces[target];
+            window.wrapper.reparent(&mut self.client, workspace.window.wid, 0, 0);
+            if floating {
+                workspace.add_floating(window);
+            } else {
+                workspace.add(window, self.tile_direction);
             }
         }
+        set_property32(&mut self.client, wid, self.atoms._NET_WM_DESKTOP, DefaultAtom::Cardinal.val(), &[target as u32]);
+        self.update_desktop_names(); // Urgency moves with the window
+
+        // Removing the window focused another one in its place
+        if source == self.current_workspace {
+            let next = self.get_focused().map(|wrapped| wrapped.window.wid);
+            match next {
+                Some(wid) => self.focus_window(wid),
+                None => self.update_active_window()
+            };
+        }
 
-        return None;
+        self.tile();
+        return true;
     }
 
     /**
@@ -349,6 +1649,7 @@ impl WindowManager {
     pub fn run(&mut self) {
         loop {
             let message = self.client.wait_for_message();
+
             match message {
                 ServerResponse::Error(error, sequence_number) => {
                     println!("Got error {}: {:?}", sequence_number, error);
@@ -370,59 +1671,78 @@ impl WindowManager {
                             self.set_focused(wid);
                         },
                         ServerEvent::EnterNotify { detail, time, root, event, child, root_x, root_y, event_x, event_y, state, mode, same_screen, focus } => {
-                            self.set_focused(event);
-                            self.tile();
+                            self.last_time = time;
+                            match self.workspaces.iter().position(|workspace| workspace.window.wid == event).and_then(|index| self.monitor_of(index)) {
+                                Some(monitor) => { self.focus_monitor(monitor); },
+                                None => {
+                                    self.set_focused(event);
+                                    self.tile();
+                                }
+                            };
                         },
                         ServerEvent::KeyPress { key_code, time, root, event, child, root_x, root_y, event_x, event_y, state, same_screen } => {
-                            if state.contains(&self.settings.mod_key) { // Windows key
-                                // TODO: Get these bindings from the config
-                                if key_code >= 10 && key_code <= 18 { // Workspaces: 0-9 (no 0 for now)
-                                    let id = key_code as u32 - 9;
-                                    let mut index = self.workspaces.len();
-
-                                    for (i, workspace) in self.workspaces.iter().enumerate() {
-                                        if workspace.id == id {
-                                            index = i;
-                                            break;
-                                        }
-                                    }
-
-                                    if index == self.workspaces.len() {
-                                        self.create_workspace(id, 0); // TODO: Use current screen
-                                    } else {
-                                        self.set_workspace(index);
-                                    }
-                                } else if key_code == 43 { // Horizontal tiling: H
-                                    self.tile_direction = TiledDirection::Horizontal;
-                                } else if key_code == 55 { // Vertical tiling: V
-                                    self.tile_direction = TiledDirection::Vertical;
-                                } else if key_code == 36 {
-                                    match Command::new("xeyes").env("DISPLAY", self.display.clone()).spawn() {
-                                        Ok(_) => (),
-                                        Err(err) => println!("Failed to start process! {}", err)
-                                    };
-                                } else if key_code == 24 && state.contains(&KeyButton::Shift) { // Kill window: Q
-                                    println!("Kill it!");
-                                    let mut wid = u32::max_value();
-                                    {
-                                        match self.get_focused() {
-                                            Some(wrapper) => wid = wrapper.window.wid,
-                                            None => ()
-                                        };
-                                    }
-
-                                    if wid != u32::max_value() {
-                                        println!("Yes");
-                                        self.client.kill_client(wid);
-                                    } else {
-                                        println!("No");
-                                    }
-                                }
+                            self.last_time = time;
+
+                            let keysym = self.keymap.keysym(key_code);
+                            let action = {
+                                let bindings = if self.resize_mode {&self.settings.resize_bindings} else {&self.settings.key_bindings};
+                                find_action(bindings, keysym, |modifier| state.contains(modifier)).cloned()
+                            };
+
+                            match action {
+                                Some(action) => self.run_action(action),
+                                None => ()
+                            };
+                        },
+                        ServerEvent::MappingNotify { request, first_keycode, count } => {
+                            // The keyboard layout changed
+                            self.keymap = Keymap::get(&mut self.client);
+                            self.grab_keys();
+                        },
+                        ServerEvent::ButtonPress { button, time, root, event, child, root_x, root_y, event_x, event_y, state, same_screen } => {
+                            self.last_time = time;
+                            if self.workspaces.iter().any(|workspace| workspace.window.wid == event) {
+                                // Grabbed on the workspace window, the child is the wrapper
+                                self.start_drag(child, button, root_x, root_y);
+                            } else {
+                                // Grabbed synchronously on the wrapper, so the pointer stays frozen until events are allowed
+                                // Let the client have the click too, unless the title bar took it
+                                let mode = if self.click(event, button, event_x, event_y, time) { AllowEventsMode::AsyncPointer } else { AllowEventsMode::ReplayPointer };
+                                self.client.allow_events(mode, time);
                             }
                         },
+                        ServerEvent::MotionNotify { detail, time, root, event, child, root_x, root_y, event_x, event_y, state, same_screen } => {
+                            self.drag_to(root_x, root_y);
+                        },
+                        ServerEvent::ButtonRelease { button, time, root, event, child, root_x, root_y, event_x, event_y, state, same_screen } => {
+                            self.last_time = time;
+                            self.end_drag(child);
+                        },
                         ServerEvent::PropertyNotify { window, atom, time, state } => {
-                            if atom == DefaultAtom::WmName.val() || atom == self.ATOM__NET_WM_NAME {
+                            self.last_time = time;
+                            if atom == DefaultAtom::WmName.val() || atom == self.atoms._NET_WM_NAME {
                                 self.update_window_name(window, true);
+                            } else if atom == DefaultAtom::WmHints.val() || atom == self.atoms.WM_PROTOCOLS {
+                                self.update_window_focus_model(window);
+                                self.update_window_urgency(window);
+                            } else if atom == DefaultAtom::WmNormalHints.val() {
+                                self.update_window_size_hints(window);
+                            } else if atom == self.atoms._NET_WM_STRUT_PARTIAL || atom == self.atoms._NET_WM_STRUT {
+                                self.update_dock_strut(window);
+                            }
+                        },
+                        ServerEvent::ConfigureRequest { stack_mode, parent, window, sibling, x, y, width, height, border_width, value_mask } => {
+                            self.configure_request(window, x, y, width, height, border_width, sibling, stack_mode, value_mask);
+                        },
+                        ServerEvent::FocusIn { detail, event, mode } => {
+                            if is_focus_change(mode, detail, true) {
+                                self.focus_in(event);
+                            }
+                        },
+                        ServerEvent::FocusOut { detail, event, mode } => {
+                            // Given back by update_input_focus unless another window takes it
+                            if is_focus_change(mode, detail, false) && event == self.input_focus {
+                                self.input_focus = 0;
                             }
                         },
                         ServerEvent::Expose { window, x, y, width, height, count } => {
@@ -435,6 +1755,24 @@ impl WindowManager {
                             self.destroy_window(window);
                             self.tile();
                         },
+                        ServerEvent::RandrScreenChangeNotify { .. } => {
+                            // A monitor was plugged in, unplugged or resized
+                            self.update_monitors();
+                        },
+                        ServerEvent::ClientMessage { format, window, message_type, data } => {
+                            if message_type == self.atoms._NET_CURRENT_DESKTOP {
+                                let index = client_message_value(&data, 0) as usize;
+                                self.set_workspace(index);
+                            } else if message_type == self.atoms._NET_WM_DESKTOP {
+                                // 0xFFFFFFFF (all desktops) isn't a workspace, so it's ignored
+                                let index = client_message_value(&data, 0) as usize;
+                                self.move_window_to_workspace(window, index);
+                            } else if message_type == self.atoms._NET_WM_STATE {
+                                self.wm_state_message(window, &data);
+                            } else if message_type == self.atoms._NET_ACTIVE_WINDOW {
+                                self.activate_window(window);
+                            }
+                        },
                         _ => () // TODO: More events
                     };
                 }
@@ -444,19 +1782,374 @@ impl WindowManager {
 
 }
 
+/** What a mouse drag does. */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DragKind {
+    Move, // Floating window
+    Resize, // Floating window
+    Swap, // Tile, swapped with the one it is dropped on
+    Split { flip_x: bool, flip_y: bool } // Tile, resized along with its neighbour. Flipped axes grow the other way
+}
+
+/** A button at the right of a window's title bar. */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TitleButton {
+    Close,
+    Float, // Toggles floating
+    Fullscreen
+}
+
+/** A mouse drag in progress. */
+pub struct Drag {
+    wid: u32, // The dragged client window
+    kind: DragKind,
+    pointer: (i16, i16), // Where the drag started (or split drags last moved), relative to the root
+    geometry: Geometry // The window's floating geometry when the drag started
+}
+
 pub struct Workspace {
     id: u32,
     window: Window,
-    tiling: Tiled
+    geometry: Geometry, // Where the workspace window was last shown (its monitor), relative to the root
+    area: Geometry, // The part of it docks leave free, relative to the workspace window
+    layout: Box<dyn Layout>,
+    floating: Vec<ManagedWindow>, // Kept above the tiles, last on top
+    floating_dirty: bool // If the floating windows need to be placed again
 }
 impl Workspace {
+    /** Returns the area windows are tiled in, relative to the workspace window. */
+    pub fn area(&self) -> Geometry {
+        return self.area;
+    }
+
+    /** Returns the whole workspace window, docks included, relative to itself. Fullscreen windows fill it. */
+    pub fn bounds(&self) -> Geometry {
+        Geometry {
+            x: 0,
+            y: 0,
+            width: self.geometry.width,
+            height: self.geometry.height
+        }
+    }
+
+    /** Tiles the layout if anything changed (or force is set), and puts the floating windows back above it. */
+    pub fn tile(&mut self, client: &mut XClient, settings: &Settings, force: bool) {
+        let restack = force || self.floating_dirty || self.layout.is_dirty();
+        let area = self.area();
+        let bounds = self.bounds();
+        self.layout.tile(client, settings, area, force);
+
+        if restack {
+            // Maximized tiles cover the layout
+            for wid in self.maximized_tiles() {
+                let wrapped = self.layout.get_window_mut(wid).unwrap();
+                wrapped.place(client, &TileAssignment::new(settings, wid, area));
+            }
+
+            for wrapped in self.floating.iter_mut() {
+                let wid = wrapped.window.wid;
+                if wrapped.maximized && !wrapped.fullscreen {
+                    wrapped.place(client, &TileAssignment::new(settings, wid, area));
+                } else {
+                    wrapped.apply(client, &TileAssignment::new(settings, wid, wrapped.floating));
+                }
+            }
+
+            // Fullscreen windows go above everything else
+            let fullscreen: Vec<u32> = self.windows().iter().filter(|wrapped| wrapped.fullscreen).map(|wrapped| wrapped.window.wid).collect();
+            for wid in fullscreen {
+                let wrapped = self.get_window_mut(wid).unwrap();
+                wrapped.place(client, &TileAssignment::fullscreen(wid, bounds));
+            }
+
+            self.floating_dirty = false;
+        }
+    }
+
+    /** Returns the maximized tiles in the order they are stacked, the focused one on top. */
+    fn maximized_tiles(&self) -> Vec<u32> {
+        let mut maximized: Vec<(bool, u32)> = self.layout.windows().iter()
+            .filter(|wrapped| wrapped.maximized && !wrapped.fullscreen)
+            .map(|wrapped| (wrapped.focused, wrapped.window.wid))
+            .collect();
+        maximized.sort();
+        return maximized.into_iter().map(|(_, wid)| wid).collect();
+    }
+
+    /**
+     * Returns every window from bottom to top, the way tile() stacks them:
+     * hidden tabs, tiles, maximized tiles, floating windows and then fullscreen windows.
+     */
+    pub fn stacking_order(&self, settings: &Settings) -> Vec<u32> {
+        let covering = |wid: u32| self.get_window(wid).map(|wrapped| wrapped.maximized || wrapped.fullscreen).unwrap_or(false);
+        let assignments = self.layout.layout(settings, self.area());
+
+        let mut order: Vec<u32> = assignments.iter().filter(|assignment| !assignment.visible && !covering(assignment.wid)).map(|assignment| assignment.wid).collect();
+        order.extend(assignments.iter().filter(|assignment| assignment.visible && !covering(assignment.wid)).map(|assignment| assignment.wid));
+        order.extend(self.maximized_tiles());
+        order.extend(self.floating.iter().filter(|wrapped| !wrapped.fullscreen).map(|wrapped| wrapped.window.wid));
+        order.extend(self.windows().iter().filter(|wrapped| wrapped.fullscreen).map(|wrapped| wrapped.window.wid));
+        return order;
+    }
+
+    /**
+     * Moves and resizes the workspace window to the given monitor geometry and maps it, tiling within the given work area.
+     * Both are relative to the root. Windows are placed again if either size changed.
+     */
+    pub fn show(&mut self, client: &mut XClient, geometry: Geometry, area: Geometry) {
+        let area = Geometry { x: area.x - geometry.x, y: area.y - geometry.y, width: area.width, height: area.height };
+        if self.geometry.width != geometry.width || self.geometry.height != geometry.height || self.area != area {
+            self.mark_dirty();
+        }
+        self.geometry = geometry;
+        self.area = area;
+
+        self.window.configure_multiple(
+            client,
+            vec![
+                WindowConfigureValue::X(geometry.x),
+                WindowConfigureValue::Y(geometry.y),
+                WindowConfigureValue::Width(geometry.width),
+                WindowConfigureValue::Height(geometry.height)
+            ]
+        );
+        self.window.map(client);
+    }
+
+    /** Returns true if a window on this workspace is fullscreen. */
+    pub fn has_fullscreen(&self) -> bool {
+        return self.windows().iter().any(|wrapped| wrapped.fullscreen);
+    }
+
+    /** Returns an urgent window on this workspace, if any. A workspace is urgent when one of its windows is. */
+    pub fn urgent_window(&self) -> Option<u32> {
+        return self.windows().iter().find(|wrapped| wrapped.is_urgent()).map(|wrapped| wrapped.window.wid);
+    }
+
+    /** Makes every window get placed again on the next tile. */
+    pub fn mark_dirty(&mut self) {
+        self.layout.mark_dirty();
+        self.floating_dirty = true;
+    }
+
+    /** Makes the given window fullscreen or not. Returns true if it changed. */
+    pub fn set_fullscreen(&mut self, wid: u32, fullscreen: bool) -> bool {
+        match self.get_window_mut(wid) {
+            Some(wrapped) => {
+                if wrapped.fullscreen == fullscreen {
+                    return false;
+                }
+
+                wrapped.fullscreen = fullscreen;
+            },
+            None => return false
+        };
+
+        // Everything is placed again, so the window gets its tile back
+        self.mark_dirty();
+        return true;
+    }
+
+    /** Makes the given window maximized or not. Returns true if it changed. */
+    pub fn set_maximized(&mut self, wid: u32, maximized: bool) -> bool {
+        match self.get_window_mut(wid) {
+            Some(wrapped) => {
+                if wrapped.maximized == maximized {
+                    return false;
+                }
+
+                wrapped.maximized = maximized;
+            },
+            None => return false
+        };
+
+        self.mark_dirty();
+        return true;
+    }
+
+    /** Adds a window to the layout and focuses it. */
+    pub fn add(&mut self, window: ManagedWindow, direction: TiledDirection) {
+        // The layout adds next to its focused window, so give it one back
+        if self.unfocus_floating() {
+            let first = self.layout.windows().first().map(|wrapped| wrapped.window.wid);
+            match first {
+                Some(wid) => { self.layout.set_focused(wid); },
+                None => ()
+            };
+        }
+
+        self.layout.add(window, direction);
+    }
+
+    /** Adds a floating window above the others and focuses it. */
+    pub fn add_floating(&mut self, mut window: ManagedWindow) {
+        self.unfocus_floating();
+        self.layout.set_focused(0); // Unfocus the tiles
+
+        window.focused = true;
+        self.floating.push(window);
+        self.floating_dirty = true;
+    }
+
+    /** Removes the given window, tiled or floating. If it was focused, another window is focused. */
+    pub fn remove(&mut self, wid: u32) -> Option<ManagedWindow> {
+        match self.floating.iter().position(|wrapped| wrapped.window.wid == wid) {
+            Some(index) => {
+                let removed = self.floating.remove(index);
+                self.floating_dirty = true;
+
+                if removed.focused {
+                    let next = match self.floating.last() {
+                        Some(wrapped) => Some(wrapped.window.wid),
+                        None => self.layout.windows().first().map(|wrapped| wrapped.window.wid)
+                    };
+
+                    match next {
+                        Some(wid) => { self.set_focused(wid); },
+                        None => ()
+                    };
+                }
+
+                return Some(removed);
+            },
+            None => ()
+        };
+
+        let removed = self.layout.remove(wid);
+
+        // The layout focuses one of its own windows when the focused one goes, but a focused floating window keeps the focus
+        if self.floating.iter().any(|wrapped| wrapped.focused) {
+            self.layout.set_focused(0);
+        }
+
+        return removed;
+    }
+
+    /** Moves a window between the layout and the floating windows, keeping it focused. Returns true if it was found. */
+    pub fn toggle_floating(&mut self, wid: u32, direction: TiledDirection) -> bool {
+        let floating = self.is_floating(wid);
+        let window = match self.remove(wid) {
+            Some(window) => window,
+            None => return false
+        };
+
+        if floating {
+            self.add(window, direction);
+        } else {
+            self.add_floating(window);
+        }
+
+        return true;
+    }
+
+    /** Returns true if the given window (or wrapper) is floating. */
+    pub fn is_floating(&self, wid: u32) -> bool {
+        return self.floating.iter().any(|wrapped| wrapped.window.wid == wid || wrapped.wrapper.wid == wid);
+    }
+
+    /** Sets where a floating window's wrapper goes. Returns true if the window is floating. */
+    pub fn set_floating_geometry(&mut self, wid: u32, geometry: Geometry) -> bool {
+        match self.floating.iter_mut().find(|wrapped| wrapped.window.wid == wid || wrapped.wrapper.wid == wid) {
+            Some(wrapped) => {
+                wrapped.floating = geometry;
+                self.floating_dirty = true;
+                return true;
+            },
+            None => return false
+        };
+    }
+
+    /** Unfocuses every floating window. Returns true if one was focused. */
+    fn unfocus_floating(&mut self) -> bool {
+        let mut unfocused = false;
+
+        for wrapped in self.floating.iter_mut() {
+            if wrapped.focused {
+                wrapped.focused = false;
+                unfocused = true;
+            }
+        }
+
+        if unfocused {
+            self.floating_dirty = true;
+        }
+
+        return unfocused;
+    }
+
+    /** Returns every window, tiled ones first. */
+    pub fn windows(&self) -> Vec<&ManagedWindow> {
+        let mut windows = self.layout.windows();
+        windows.extend(self.floating.iter());
+        return windows;
+    }
+
+    /** Returns the given window given its ID (or its wrapper's ID), tiled or floating */
+    pub fn get_window(&self, wid: u32) -> Option<&ManagedWindow> {
+        match self.floating.iter().find(|wrapped| wrapped.window.wid == wid || wrapped.wrapper.wid == wid) {
+            Some(wrapped) => return Some(wrapped),
+            None => return self.layout.get_window(wid)
+        };
+    }
+
+    /** Returns the given window given its ID (or its wrapper's ID), tiled or floating */
+    pub fn get_window_mut(&mut self, wid: u32) -> Option<&mut ManagedWindow> {
+        match self.floating.iter_mut().find(|wrapped| wrapped.window.wid == wid || wrapped.wrapper.wid == wid) {
+            Some(wrapped) => return Some(wrapped),
+            None => return self.layout.get_window_mut(wid)
+        };
+    }
+
+    /**
+     * Makes the given window focused, unfocusing the others. Focusing a floating window raises it.
+     * Returns true if the window was found.
+     */
+    pub fn set_focused(&mut self, wid: u32) -> bool {
+        match self.floating.iter().position(|wrapped| wrapped.window.wid == wid || wrapped.wrapper.wid == wid) {
+            Some(index) => {
+                let mut window = self.floating.remove(index);
+                self.unfocus_floating();
+                self.layout.set_focused(0); // Unfocus the tiles
+
+                window.focused = true;
+                self.floating.push(window);
+                self.floating_dirty = true;
+                return true;
+            },
+            None => ()
+        };
+
+        if self.layout.get_window(wid).is_none() {
+            return false;
+        }
+
+        self.unfocus_floating();
+        return self.layout.set_focused(wid);
+    }
+
+    /** Returns the focused window, tiled or floating, or None */
+    pub fn get_focused(&self) -> Option<&ManagedWindow> {
+        match self.floating.iter().find(|wrapped| wrapped.focused) {
+            Some(wrapped) => return Some(wrapped),
+            None => return self.layout.get_focused()
+        };
+    }
+
+    /** Returns the focused window, tiled or floating, or None */
+    pub fn get_focused_mut(&mut self) -> Option<&mut ManagedWindow> {
+        match self.floating.iter_mut().find(|wrapped| wrapped.focused) {
+            Some(wrapped) => return Some(wrapped),
+            None => return self.layout.get_focused_mut()
+        };
+    }
+
     pub fn paint_background(&self, client: &mut XClient, gc: &mut GraphicsContext, settings: &Settings) {
         gc.set_fg(client, &settings.background_color);
         self.window.fill_rect(client, gc.gcid, Rectangle {
             x: 0,
             y: 0,
-            width: self.window.width,
-            height: self.window.height
+            width: self.geometry.width,
+            height: self.geometry.height
         });
     }
 }
@@ -466,17 +2159,185 @@ pub struct ManagedWindow {
     pub window: Window,
     pub wrapper: Window,
     pub focused: bool,
+    pub active: bool, // Shown child of a tabbed or stacked container
+    pub title: Geometry, // Where the title bar is drawn, relative to the wrapper
+    pub floating: Geometry, // Where the wrapper goes while floating, relative to the workspace window
+    pub placed: Geometry, // Where the client window was last placed, relative to the workspace window
+    pub fullscreen: bool, // Covers the whole workspace, ignoring its tile or floating geometry
+    pub maximized: bool, // Fills the workspace like a lone tile, ignoring its tile or floating geometry
+    pub urgent: bool, // WM_HINTS urgency flag
+    pub demands_attention: bool, // _NET_WM_STATE_DEMANDS_ATTENTION, cleared when focused
+    pub size_hints: SizeHints, // WM_NORMAL_HINTS
+    pub accepts_input: bool, // WM_HINTS input field: whether SetInputFocus is used
+    pub takes_focus: bool, // WM_PROTOCOLS has WM_TAKE_FOCUS
     parent: Window,
     name: String
 }
 impl ManagedWindow {
+    /**
+     * Reads the window's ICCCM focus model from WM_HINTS and WM_PROTOCOLS.
+     */
+    pub fn update_focus_model(&mut self, client: &mut XClient, atoms: &Atoms) {
+        // Clients that don't set the input hint are given the focus
+        let hints = get_property32(client, self.window.wid, DefaultAtom::WmHints.val(), DefaultAtom::WmHints.val());
+        self.accepts_input = hints.len() < 2 || hints[0] & 1 == 0 || hints[1] != 0; // Flag 1 = InputHint
+
+        let protocols = get_property32(client, self.window.wid, atoms.WM_PROTOCOLS, DefaultAtom::Atom.val());
+        self.takes_focus = protocols.contains(&atoms.WM_TAKE_FOCUS);
+    }
+
+    /**
+     * Places the wrapper and window as given by the assignment, unless the window is fullscreen or maximized.
+     */
+    pub fn apply(&mut self, client: &mut XClient, assignment: &TileAssignment) {
+        // Workspace::tile places fullscreen and maximized windows
+        if self.fullscreen || self.maximized {
+            return;
+        }
+
+        self.place(client, assignment);
+    }
+
+    /**
+     * Places the wrapper and window as given by the assignment, stacked above the windows placed before it, and repaints.
+     */
+    pub fn place(&mut self, client: &mut XClient, assignment: &TileAssignment) {
+        if !assignment.visible {
+            self.wrapper.unmap(client);
+            return;
+        }
+
+        self.title = assignment.title;
+        self.active = assignment.active;
+
+        // Clients that only take some sizes are centred in their space. Ones bigger than it are cut off at the right and bottom.
+        // Fullscreen ignores the hints, as EWMH asks
+        let (width, height) = if self.fullscreen {
+            (assignment.window.width, assignment.window.height)
+        } else {
+            self.size_hints.constrain(assignment.window.width, assignment.window.height)
+        };
+        let mut window = Geometry { x: 0, y: 0, width, height }.centred_in(assignment.window);
+        window.x = max(window.x, assignment.window.x);
+        window.y = max(window.y, assignment.window.y);
+        self.placed = Geometry { x: assignment.wrapper.x + window.x, y: assignment.wrapper.y + window.y, width, height };
+
+        // Stack in the order of the assignments, so later title bars can cover earlier windows
+        self.wrapper.configure_multiple(
+            client,
+            vec![
+                WindowConfigureValue::X(assignment.wrapper.x),
+                WindowConfigureValue::Y(assignment.wrapper.y),
+                WindowConfigureValue::Width(assignment.wrapper.width),
+                WindowConfigureValue::Height(assignment.wrapper.height),
+                WindowConfigureValue::StackMode(StackMode::Above)
+            ]
+        );
+        self.window.configure_multiple(
+            client,
+            vec![
+                WindowConfigureValue::X(window.x),
+                WindowConfigureValue::Y(window.y),
+                WindowConfigureValue::Width(window.width),
+                WindowConfigureValue::Height(window.height)
+            ]
+        );
+        self.wrapper.map(client);
+
+        // Tell the X Server to update this window
+        client.send_event(&ServerEvent::Expose {
+            window: self.wrapper.wid,
+            x: 0,
+            y: 0,
+            width: self.wrapper.width,
+            height: self.wrapper.height,
+            count: 0
+        }, false, self.wrapper.wid, &vec![]);
+    }
+
+    /**
+     * Returns the buttons drawn at the right of the title bar and where they are, relative to the wrapper.
+     * Title bars too narrow to leave room for some of the name get none.
+     */
+    pub fn title_buttons(&self) -> Vec<(TitleButton, Geometry)> {
+        let buttons = [TitleButton::Fullscreen, TitleButton::Float, TitleButton::Close];
+        let size = self.title.height;
+        if (self.title.width as u32) < size as u32 * (buttons.len() as u32 + 2) {
+            return vec![];
+        }
+
+        let left = self.title.x + (self.title.width - size * buttons.len() as u16) as i16;
+        return buttons.iter().enumerate().map(|(i, &button)| {
+            (button, Geometry { x: left + (size * i as u16) as i16, y: self.title.y, width: size, height: size })
+        }).collect();
+    }
+
+    /** Returns the title button at the given point, relative to the wrapper, if any. */
+    pub fn title_button_at(&self, x: i16, y: i16) -> Option<TitleButton> {
+        return self.title_buttons().iter().find(|&&(_, geometry)| geometry.contains(x, y)).map(|&(button, _)| button);
+    }
+
+    /**
+     * Reads the urgency flag from WM_HINTS.
+     */
+    pub fn update_urgency(&mut self, client: &mut XClient) {
+        let hints = get_property32(client, self.window.wid, DefaultAtom::WmHints.val(), DefaultAtom::WmHints.val());
+        self.urgent = hints.len() > 0 && hints[0] & 256 != 0; // Flag 256 = UrgencyHint
+    }
+
+    /** Returns true if the window wants attention, through WM_HINTS or _NET_WM_STATE. */
+    pub fn is_urgent(&self) -> bool {
+        return self.urgent || self.demands_attention;
+    }
+
+    /**
+     * Returns the _NET_WM_STATE atoms that apply to this window.
+     */
+    pub fn wm_state(&self, atoms: &Atoms) -> Vec<u32> {
+        let mut state = Vec::new();
+        if self.fullscreen {
+            state.push(atoms._NET_WM_STATE_FULLSCREEN);
+        }
+        if self.demands_attention {
+            state.push(atoms._NET_WM_STATE_DEMANDS_ATTENTION);
+        }
+
+        return state;
+    }
+
+    /**
+     * Tells the client its real geometry with a synthetic ConfigureNotify, as ICCCM requires when its ConfigureRequest isn't honoured.
+     * origin_x and origin_y are the position of the workspace window on the root.
+     */
+    pub fn send_configure_notify(&self, client: &mut XClient, origin_x: i16, origin_y: i16) {
+        client.send_event(&ServerEvent::ConfigureNotify {
+            event: self.window.wid,
+            window: self.window.wid,
+            above_sibling: 0, // None
+            x: origin_x + self.placed.x,
+            y: origin_y + self.placed.y,
+            width: self.placed.width,
+            height: self.placed.height,
+            border_width: 0,
+            override_redirect: false
+        }, false, self.window.wid, &vec![]);
+    }
+
     pub fn paint(&mut self, client: &mut XClient, gc: &mut GraphicsContext, workspace_wid: u32, workspace_depth: u8, settings: &Settings) {
         println!("Paint! Focused: {}", self.focused);
 
+        // The client covers the whole wrapper
+        if self.fullscreen {
+            return;
+        }
+
         // Get values
         let focused = self.focused; // Just quicker to write
-        let title_bg = if focused {&settings.win_title_bg_focused} else {&settings.win_title_bg};
-        let title_fg = if focused {&settings.win_title_fg_focused} else {&settings.win_title_fg};
+        let urgent = self.is_urgent() && !focused;
+        let active = self.active && !focused;
+        let title = self.title;
+        let title_bg = if focused {&settings.win_title_bg_focused} else if urgent {&settings.win_title_bg_urgent} else if active {&settings.win_title_bg_active} else {&settings.win_title_bg};
+        let title_fg = if focused {&settings.win_title_fg_focused} else if urgent {&settings.win_title_fg_urgent} else if active {&settings.win_title_fg_active} else {&settings.win_title_fg};
         let title_border_width_l = settings.win_title_border_width_left;
         let title_border_width_t = settings.win_title_border_width_top;
         let title_border_width_r = settings.win_title_border_width_right;
@@ -489,17 +2350,37 @@ impl ManagedWindow {
         let border_width_b = settings.win_border_width_bottom;
         let border_color = if focused {&settings.win_border_color_focused} else {&settings.win_border_color};
 
-        // Title
+        // Title (may only be part of the bar if this is a tab)
         gc.set_fg(client, title_bg);
         self.wrapper.fill_rect(client, gc.gcid, Rectangle {
-            x: title_border_width_l as i16,
-            y: title_border_width_l as i16,
-            width: self.wrapper.width - title_border_width_l - title_border_width_r,
-            height: 20 - title_border_width_t - title_border_width_b
+            x: title.x + title_border_width_l as i16,
+            y: title.y + title_border_width_t as i16,
+            width: title.width.saturating_sub(title_border_width_l + title_border_width_r),
+            height: title.height.saturating_sub(title_border_width_t + title_border_width_b)
         });
 
         gc.set_fg(client, title_fg);
-        self.wrapper.img_text8(client, gc.gcid, &self.name, 0, 10);
+        self.wrapper.img_text8(client, gc.gcid, &self.name, title.x, title.y + 10);
+
+        // Buttons, drawn over the end of the name if it is long
+        for (button, geometry) in self.title_buttons() {
+            let label = match button {
+                TitleButton::Close => "X",
+                TitleButton::Float => "^",
+                TitleButton::Fullscreen => "#"
+            };
+
+            gc.set_fg(client, &settings.win_title_button_bg);
+            self.wrapper.fill_rect(client, gc.gcid, Rectangle {
+                x: geometry.x + 1,
+                y: geometry.y + 1,
+                width: geometry.width.saturating_sub(2),
+                height: geometry.height.saturating_sub(2)
+            });
+
+            gc.set_fg(client, &settings.win_title_button_fg);
+            self.wrapper.img_text8(client, gc.gcid, &label.to_string(), geometry.x + geometry.width as i16 / 2 - 3, geometry.y + 14);
+        }
 
         let lines = title_border_width_l + title_border_width_t + title_border_width_r + title_border_width_b;
         if lines > 0 {
@@ -508,34 +2389,33 @@ impl ManagedWindow {
             
             if title_border_width_l > 0 { // Left
                 rects.push(Rectangle {
-                    x: 0,
-                    y: 0,
+                    x: title.x,
+                    y: title.y,
                     width: title_border_width_l - 1,
-                    height: 20
+                    height: title.height
                 });
             }
             if title_border_width_t > 0 { // Top
                 rects.push(Rectangle {
-                    x: 0,
-                    y: 0,
-                    width: self.wrapper.width,
+                    x: title.x,
+                    y: title.y,
+                    width: title.width,
                     height: title_border_width_t - 1
                 });
             }
             if title_border_width_r > 0 { // Right
                 rects.push(Rectangle {
-                    x: self.wrapper.width as i16 - title_border_width_r as i16,
-                    y: 0,
+                    x: title.x + title.width as i16 - title_border_width_r as i16,
+                    y: title.y,
                     width: title_border_width_r - 1,
-                    height: 20
+                    height: title.height
                 });
             }
             if title_border_width_b > 0 { // Bottom
                 rects.push(Rectangle {
-                    x: 0,
-                    y: self.wrapper.height as i16 - title_border_width_b as i16,
-                    width: self.wrapper.width,
-                    //height: title_border_width_b - 1
+                    x: title.x,
+                    y: title.y + title.height as i16 - title_border_width_b as i16,
+                    width: title.width,
                     height: title_border_width_b
                 });
             }
@@ -543,6 +2423,11 @@ impl ManagedWindow {
             self.wrapper.draw_rects(client, gc.gcid, &rects);
         }
 
+        // Tabs and stacked title bars that aren't shown have nothing below the title
+        if self.wrapper.height <= TITLE_HEIGHT {
+            return;
+        }
+
         // Background
         gc.set_fg(client, bg);
         self.wrapper.fill_rect(client, gc.gcid, Rectangle {
@@ -595,6 +2480,69 @@ impl ManagedWindow {
     }
 }
 
+#[cfg(test)]
+impl ManagedWindow {
+    /** Creates a window that was never sent to an X server, for testing layouts. */
+    pub fn detached(wid: u32) -> ManagedWindow {
+        let window = |wid: u32| Window {
+            depth: 0,
+            wid,
+            parent: 0,
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+            border_width: 0,
+            class: WindowInputType::CopyFromParent,
+            visual: 0,
+            values: Vec::new()
+        };
+        let none = SizeHints { min: None, max: None, inc: None, base: None, min_aspect: None, max_aspect: None };
+
+        ManagedWindow {
+            window: window(wid),
+            wrapper: window(wid + 0x1000), // Kept apart from the client IDs tests use
+            focused: false,
+            active: false,
+            title: Geometry { x: 0, y: 0, width: 1, height: TITLE_HEIGHT },
+            floating: Geometry { x: 0, y: 0, width: 1, height: 1 },
+            placed: Geometry { x: 0, y: TITLE_HEIGHT as i16, width: 1, height: 1 },
+            fullscreen: false,
+            maximized: false,
+            urgent: false,
+            demands_attention: false,
+            size_hints: none,
+            accepts_input: true,
+            takes_focus: false,
+            parent: window(0),
+            name: String::new()
+        }
+    }
+}
+
+/**
+ * Returns every combination of the Caps Lock and Num Lock masks, so grabs can be made to work whichever are on.
+ */
+fn lock_masks() -> [u16; 4] {
+    let lock = KeyButton::Lock.val();
+    let num_lock = KeyButton::Mod2.val();
+    return [0, lock, num_lock, lock | num_lock];
+}
+
+/**
+ * Returns true if a FocusIn (focus_in is set) or FocusOut event means the focus really moved.
+ * Keyboard grabs (resize mode, key bindings) starting and ending, focus following the pointer, and a window losing
+ * the focus to its own child don't count. A window getting the focus back from a child does, such as the root after a client.
+ */
+fn is_focus_change(mode: FocusMode, detail: FocusDetail, focus_in: bool) -> bool {
+    match (mode, detail) {
+        (FocusMode::Normal, FocusDetail::Pointer) => return false,
+        (FocusMode::Normal, FocusDetail::Inferior) => return focus_in,
+        (FocusMode::Normal, _) => return true,
+        _ => return false // Grab, Ungrab, WhileGrabbed
+    };
+}
+
 fn debug_tiled_print(tiled: &mut Tiled, spacing: usize) {
     if tiled.is_dirty() {
         println!("{}TILED [DIRTY]", "  ".repeat(spacing));
@@ -0,0 +1,133 @@
+use xrb::XClient;
+use xrb::models::*;
+
+use ewmh::Strut;
+use tiling::Geometry;
+
+use std::cmp::max;
+
+/** A monitor (RandR output, or a monitor made with xrandr --setmonitor) and the workspace shown on it. */
+#[derive(Debug, Clone)]
+pub struct Monitor {
+    pub geometry: Geometry, // Relative to the root window
+    pub area: Geometry, // The geometry minus the space docks reserve, where its workspace goes
+    pub workspace: usize // Index of the workspace shown on it
+}
+impl Monitor {
+    /** Creates a monitor with nothing reserved. */
+    pub fn new(geometry: Geometry, workspace: usize) -> Monitor {
+        Monitor {
+            geometry,
+            area: geometry,
+            workspace
+        }
+    }
+
+    /**
+     * Sets the area to the geometry minus the space the given struts reserve, taking the widest one at each edge.
+     * screen_width and screen_height are the size of the root window.
+     */
+    pub fn reserve(&mut self, struts: &[Strut], screen_width: u32, screen_height: u32) {
+        let (mut left, mut right, mut top, mut bottom) = (0, 0, 0, 0);
+        for strut in struts.iter() {
+            let (l, r, t, b) = strut.reserved(self.geometry, screen_width, screen_height);
+            left = max(left, l);
+            right = max(right, r);
+            top = max(top, t);
+            bottom = max(bottom, b);
+        }
+
+        self.area = Geometry {
+            x: self.geometry.x + left as i16,
+            y: self.geometry.y + top as i16,
+            width: max(self.geometry.width.saturating_sub(left).saturating_sub(right), 1),
+            height: max(self.geometry.height.saturating_sub(top).saturating_sub(bottom), 1)
+        };
+    }
+}
+
+/**
+ * Returns true if the server has the RandR extension.
+ */
+pub fn has_randr(client: &mut XClient) -> bool {
+    let seq = client.query_extension("RANDR");
+    match client.wait_for_response(seq) {
+        ServerResponse::Reply(ServerReply::QueryExtension { present, .. }, _) => return present,
+        _ => return false
+    };
+}
+
+/**
+ * Asks RandR to send RRScreenChangeNotify events when monitors are plugged in, unplugged or resized.
+ */
+pub fn select_screen_change(client: &mut XClient, root: u32) {
+    client.randr_select_input(root, RandrNotifyMask::ScreenChange.val());
+}
+
+/**
+ * Returns the geometry of every active monitor on the given screen, primary first.
+ * randr is what has_randr() returned. Falls back to the whole screen if RandR isn't available or reports no monitors.
+ */
+pub fn query_monitors(client: &mut XClient, screen: usize, randr: bool) -> Vec<Geometry> {
+    let root = client.info.screens[screen].root;
+    let whole = Geometry {
+        x: 0,
+        y: 0,
+        width: client.info.screens[screen].width_in_pixels,
+        height: client.info.screens[screen].height_in_pixels
+    };
+
+    if !randr {
+        return vec![whole];
+    }
+
+    let seq = client.randr_get_monitors(root, true); // Active monitors only
+    let mut monitors = match client.wait_for_response(seq) {
+        ServerResponse::Reply(ServerReply::RandrGetMonitors { monitors, .. }, _) => monitors,
+        ServerResponse::Error(err, _) => {
+            println!("Failed to get monitors: {:?}", err);
+            return vec![whole];
+        },
+        _ => return vec![whole]
+    };
+
+    // Outputs that are turned off still show up with no size
+    monitors.retain(|monitor| monitor.width > 0 && monitor.height > 0);
+    monitors.sort_by_key(|monitor| (!monitor.primary, monitor.x, monitor.y));
+    if monitors.is_empty() {
+        return vec![whole];
+    }
+
+    return monitors.iter().map(|monitor| Geometry { x: monitor.x, y: monitor.y, width: monitor.width, height: monitor.height }).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strut() -> Strut {
+        Strut { left: 0, right: 0, top: 0, bottom: 0, left_range: (0, 0), right_range: (0, 0), top_range: (0, 0), bottom_range: (0, 0) }
+    }
+
+    #[test]
+    fn reserve_takes_the_widest_strut_at_each_edge() {
+        let mut monitor = Monitor::new(Geometry { x: 0, y: 0, width: 1920, height: 1080 }, 0);
+        let whole = (0, u32::max_value());
+        let struts = [
+            Strut { top: 30, top_range: whole, ..strut() },
+            Strut { top: 40, top_range: whole, left: 10, left_range: whole, ..strut() }
+        ];
+
+        monitor.reserve(&struts, 1920, 1080);
+        assert_eq!(monitor.area, Geometry { x: 10, y: 40, width: 1910, height: 1040 });
+    }
+
+    #[test]
+    fn reserve_ignores_struts_on_other_monitors() {
+        let mut monitor = Monitor::new(Geometry { x: 1920, y: 0, width: 1920, height: 1080 }, 0);
+        let struts = [Strut { top: 30, top_range: (0, 1919), ..strut() }];
+
+        monitor.reserve(&struts, 3840, 1080);
+        assert_eq!(monitor.area, monitor.geometry);
+    }
+}
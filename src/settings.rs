@@ -2,8 +2,13 @@ extern crate xrb;
 
 use xrb::models::{Color, KeyButton};
 
+use keys::{Action, KeyBinding};
+use tiling::{Direction, TiledDirection, TiledMode};
+
 pub struct Settings {
     pub mod_key: KeyButton,
+    pub key_bindings: Vec<KeyBinding>,
+    pub resize_bindings: Vec<KeyBinding>, // Used instead of key_bindings in resize mode
     pub background_color: Color,
 
     pub win_bg: Color,
@@ -20,19 +25,83 @@ pub struct Settings {
     pub win_title_fg: Color,
     pub win_title_bg_focused: Color,
     pub win_title_fg_focused: Color,
+    pub win_title_bg_active: Color, // Shown tab of a tabbed or stacked container that isn't focused
+    pub win_title_fg_active: Color,
+    pub win_title_bg_urgent: Color, // Windows that want attention and aren't focused
+    pub win_title_fg_urgent: Color,
+    pub win_title_button_bg: Color, // Close, float and fullscreen buttons at the right of the title bar
+    pub win_title_button_fg: Color,
 
     pub win_title_border_width_left: u16,
     pub win_title_border_width_top: u16,
     pub win_title_border_width_right: u16,
     pub win_title_border_width_bottom: u16,
     pub win_title_border_color: Color,
-    pub win_title_border_color_focused: Color
+    pub win_title_border_color_focused: Color,
+
+    pub urgent_workspace_suffix: String, // Added to the _NET_DESKTOP_NAMES entry of workspaces with an urgent window
+    pub swap_visible_workspaces: bool, // Switching to a workspace shown on another monitor swaps the two, instead of focusing that monitor
+    pub resize_step: i16, // Pixels a tile grows or shrinks per resize key press
+    pub double_click_time: u32, // Milliseconds between two title bar clicks for them to toggle maximize
+    pub close_timeout: u32 // Milliseconds after asking a window to close that closing it again kills it, instead of asking again
 }
 
 impl Settings {
     pub fn default() -> Settings {
+        let mod_key = KeyButton::Mod4;
+        let mod_shift = [mod_key.clone(), KeyButton::Shift];
+        let mod_ctrl = [mod_key.clone(), KeyButton::Control];
+
+        let mut key_bindings = vec![
+            KeyBinding::new(&[mod_key.clone()], "Return", Action::Spawn(String::from("xeyes"))),
+            KeyBinding::new(&mod_shift, "q", Action::Close), // Asks the window to close. Pressing it again soon after kills it
+            KeyBinding::new(&[mod_key.clone()], "r", Action::ResizeMode),
+            KeyBinding::new(&[mod_key.clone()], "w", Action::ContainerMode(TiledMode::Tabbed)),
+            KeyBinding::new(&[mod_key.clone()], "s", Action::ContainerMode(TiledMode::Stacked)),
+            KeyBinding::new(&[mod_key.clone()], "e", Action::ContainerMode(TiledMode::Split)),
+            KeyBinding::new(&[mod_key.clone()], "space", Action::CycleLayout),
+            KeyBinding::new(&mod_shift, "space", Action::ToggleFloating),
+            KeyBinding::new(&[mod_key.clone()], "f", Action::ToggleFullscreen),
+            KeyBinding::new(&[mod_key.clone()], "u", Action::FocusUrgent),
+            KeyBinding::new(&[mod_key.clone()], "comma", Action::FocusMonitor(-1)),
+            KeyBinding::new(&[mod_key.clone()], "period", Action::FocusMonitor(1)),
+            KeyBinding::new(&mod_shift, "comma", Action::MoveToMonitor(-1)),
+            KeyBinding::new(&mod_shift, "period", Action::MoveToMonitor(1)),
+            KeyBinding::new(&[mod_key.clone()], "b", Action::TileDirection(TiledDirection::Horizontal)),
+            KeyBinding::new(&[mod_key.clone()], "v", Action::TileDirection(TiledDirection::Vertical))
+        ];
+        let mut resize_bindings = vec![
+            KeyBinding::new(&[], "Escape", Action::ExitResizeMode),
+            KeyBinding::new(&[], "Return", Action::ExitResizeMode)
+        ];
+
+        // Workspaces 1 to 9, Shift moves the focused window there
+        for id in 1..10 {
+            let key = id.to_string();
+            key_bindings.push(KeyBinding::new(&[mod_key.clone()], &key, Action::Workspace(id)));
+            key_bindings.push(KeyBinding::new(&mod_shift, &key, Action::MoveToWorkspace(id)));
+        }
+
+        // Arrows or hjkl focus, move with Shift and resize with Ctrl (or alone in resize mode)
+        let directions = [
+            ("Left", "h", Direction::Left),
+            ("Down", "j", Direction::Down),
+            ("Up", "k", Direction::Up),
+            ("Right", "l", Direction::Right)
+        ];
+        for &(arrow, letter, direction) in directions.iter() {
+            for key in [arrow, letter].iter() {
+                key_bindings.push(KeyBinding::new(&[mod_key.clone()], key, Action::Focus(direction)));
+                key_bindings.push(KeyBinding::new(&mod_shift, key, Action::Move(direction)));
+                key_bindings.push(KeyBinding::new(&mod_ctrl, key, Action::Resize(direction)));
+                resize_bindings.push(KeyBinding::new(&[], key, Action::Resize(direction)));
+            }
+        }
+
         Settings {
-            mod_key: KeyButton::Mod4,
+            mod_key,
+            key_bindings,
+            resize_bindings,
             background_color: Color::from_num(0x444444),
 
             win_bg: Color::from_num(0x000000),
@@ -49,6 +118,12 @@ impl Settings {
             win_title_fg: Color::from_num(0xFFFFFF),
             win_title_bg_focused: Color::from_num(0x111111),
             win_title_fg_focused: Color::from_num(0xFFFFFF),
+            win_title_bg_active: Color::from_num(0x3B4245),
+            win_title_fg_active: Color::from_num(0xFFFFFF),
+            win_title_bg_urgent: Color::from_num(0x900000),
+            win_title_fg_urgent: Color::from_num(0xFFFFFF),
+            win_title_button_bg: Color::from_num(0x222222),
+            win_title_button_fg: Color::from_num(0xFFFFFF),
 
             win_title_border_width_left: 1,
             win_title_border_width_top: 1,
@@ -57,7 +132,13 @@ impl Settings {
             //win_title_border_color: Color::from_num(0x000000),
             win_title_border_color: Color::from_num(0xFF0000),
             //win_title_border_color_focused: Color::from_num(0x666666)
-            win_title_border_color_focused: Color::from_num(0x00FF00)
+            win_title_border_color_focused: Color::from_num(0x00FF00),
+
+            urgent_workspace_suffix: String::from("!"),
+            swap_visible_workspaces: true,
+            resize_step: 20,
+            double_click_time: 400,
+            close_timeout: 5000
         }
     }
 }
@@ -1,49 +1,212 @@
-use std::mem::swap;
+use std::cmp::max;
+use std::mem::{replace, swap};
 
+use layout::{Layout, LayoutKind};
 use manager::ManagedWindow;
 use settings::Settings;
 
-use xrb::XClient;
-use xrb::models::*;
+/** Height of the title bar drawn above every window, in pixels. */
+pub const TITLE_HEIGHT: u16 = 20;
+
+/** The smallest a tile may be resized to along its split axis (title bar + border + at least 1px of window). */
+pub const MIN_TILE_SIZE: u16 = TITLE_HEIGHT + 2;
+
+/** A rectangle, relative to whatever window contains it. */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Geometry {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16
+}
+impl Geometry {
+    /** Returns this geometry moved so its centre is the centre of the given area. */
+    pub fn centred_in(&self, area: Geometry) -> Geometry {
+        Geometry {
+            x: area.x + (area.width as i16 - self.width as i16) / 2,
+            y: area.y + (area.height as i16 - self.height as i16) / 2,
+            width: self.width,
+            height: self.height
+        }
+    }
+
+    /** Returns true if the given point is inside this geometry. */
+    pub fn contains(&self, x: i16, y: i16) -> bool {
+        return x >= self.x && y >= self.y && (x as i32) < self.x as i32 + self.width as i32 && (y as i32) < self.y as i32 + self.height as i32;
+    }
+}
+
+/** Where a managed window should be placed, as computed by Tiled::layout. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileAssignment {
+    pub wid: u32, // The client window's ID
+    pub wrapper: Geometry, // Relative to the workspace window
+    pub window: Geometry, // Relative to the wrapper
+    pub title: Geometry, // Where the title bar is drawn, relative to the wrapper
+    pub active: bool, // If this is the shown child of a tabbed or stacked container
+    pub visible: bool // If false the wrapper is unmapped and the geometry is ignored
+}
+impl TileAssignment {
+    /** Creates an assignment for a window that fills the given area, with a full title bar. */
+    pub fn new(settings: &Settings, wid: u32, area: Geometry) -> TileAssignment {
+        TileAssignment {
+            wid,
+            wrapper: area,
+            window: client_geometry(settings, area.width, area.height),
+            title: Geometry { x: 0, y: 0, width: area.width, height: TITLE_HEIGHT },
+            active: false,
+            visible: true
+        }
+    }
+
+    /**
+     * Returns the area this assignment covers when looking for neighbours, relative to the workspace window.
+     * Shown tabs are represented by their tab, so moving left and right (or up and down for stacked) goes between tabs.
+     */
+    pub fn footprint(&self) -> Geometry {
+        if self.active {
+            return Geometry {
+                x: self.wrapper.x + self.title.x,
+                y: self.wrapper.y + self.title.y,
+                width: self.title.width,
+                height: self.title.height
+            };
+        }
+
+        return self.wrapper;
+    }
+
+    /** Creates an assignment that hides the given window. */
+    pub fn hidden(wid: u32) -> TileAssignment {
+        let empty = Geometry { x: 0, y: 0, width: 0, height: 0 };
+        TileAssignment {
+            wid,
+            wrapper: empty,
+            window: empty,
+            title: empty,
+            active: false,
+            visible: false
+        }
+    }
+
+    /** Creates an assignment for a fullscreen window: the client fills the whole area, without a title bar or borders. */
+    pub fn fullscreen(wid: u32, area: Geometry) -> TileAssignment {
+        TileAssignment {
+            wid,
+            wrapper: area,
+            window: Geometry { x: 0, y: 0, width: area.width, height: area.height },
+            title: Geometry { x: 0, y: 0, width: 0, height: 0 },
+            active: false,
+            visible: true
+        }
+    }
+}
+
+/** Returns where a client window goes inside a wrapper of the given size, below the title bar and inside the borders. */
+pub fn client_geometry(settings: &Settings, width: u16, height: u16) -> Geometry {
+    let left = settings.win_border_width_left;
+    let top = settings.win_border_width_top;
+    let right = settings.win_border_width_right;
+    let bottom = settings.win_border_width_bottom;
+
+    Geometry {
+        x: left as i16,
+        y: (TITLE_HEIGHT + top) as i16,
+        width: max(1, width.saturating_sub(left + right)), // X doesn't allow empty windows
+        height: max(1, height.saturating_sub(TITLE_HEIGHT + top + bottom))
+    }
+}
+
+/**
+ * Finds the visible window closest to the given window in the given direction.
+ * Windows that line up with the given window are preferred, then the closest ones, then the most centered ones.
+ */
+pub fn find_neighbour(assignments: &Vec<TileAssignment>, wid: u32, direction: Direction) -> Option<u32> {
+    let from = match assignments.iter().find(|assignment| assignment.wid == wid && assignment.visible) {
+        Some(assignment) => assignment.footprint(),
+        None => return None
+    };
+
+    let mut best = None;
+    let mut best_score = (true, i32::max_value(), i32::max_value());
+
+    for assignment in assignments.iter() {
+        if assignment.wid == wid || !assignment.visible {
+            continue;
+        }
+
+        let to = assignment.footprint();
+        let (from_x1, from_y1, from_x2, from_y2) = (from.x as i32, from.y as i32, from.x as i32 + from.width as i32, from.y as i32 + from.height as i32);
+        let (to_x1, to_y1, to_x2, to_y2) = (to.x as i32, to.y as i32, to.x as i32 + to.width as i32, to.y as i32 + to.height as i32);
+
+        // Distance to travel, and how the two line up on the other axis
+        let (gap, overlap, offset) = match direction {
+            Direction::Left => (from_x1 - to_x2, from_y2.min(to_y2) - from_y1.max(to_y1), (from_y1 + from_y2) - (to_y1 + to_y2)),
+            Direction::Right => (to_x1 - from_x2, from_y2.min(to_y2) - from_y1.max(to_y1), (from_y1 + from_y2) - (to_y1 + to_y2)),
+            Direction::Up => (from_y1 - to_y2, from_x2.min(to_x2) - from_x1.max(to_x1), (from_x1 + from_x2) - (to_x1 + to_x2)),
+            Direction::Down => (to_y1 - from_y2, from_x2.min(to_x2) - from_x1.max(to_x1), (from_x1 + from_x2) - (to_x1 + to_x2))
+        };
+
+        if gap < 0 { // Not in that direction
+            continue;
+        }
+
+        let score = (overlap <= 0, gap, offset.abs());
+        if score < best_score {
+            best_score = score;
+            best = Some(assignment.wid);
+        }
+    }
+
+    return best;
+}
+
+/** Splits length pixels into count (almost) equal parts. The last part gets whatever rounding left over. */
+pub fn split_even(length: u16, count: usize) -> Vec<u16> {
+    let mut sizes = Vec::with_capacity(count);
+    let mut used = 0;
+
+    for i in 0..count {
+        let size = if i == count - 1 { length - used } else { length / count as u16 };
+        used += size;
+        sizes.push(size);
+    }
+
+    return sizes;
+}
 
 #[derive(Debug)]
 pub struct Tiled {
     pub children: Vec<TiledChild>,
+    weights: Vec<f32>, // Relative size of each child, same order as children
     direction: TiledDirection,
-    offset: i16, // Pixels
+    mode: TiledMode,
+    active: usize, // The child shown in tabbed and stacked mode when the focus is elsewhere
     dirty: bool // If this should be redrawn
 }
 impl Tiled {
     pub fn new_0(direction: TiledDirection) -> Tiled {
         Tiled {
             children: Vec::with_capacity(2),
+            weights: Vec::with_capacity(2),
             direction,
-            offset: 0,
+            mode: TiledMode::Split,
+            active: 0,
             dirty: true
         }
     }
 
     pub fn new_1(window: ManagedWindow, direction: TiledDirection) -> Tiled {
-        let mut vec = Vec::with_capacity(2);
-        vec.push(TiledChild::Window(window));
-        Tiled {
-            children: vec,
-            direction,
-            offset: 0,
-            dirty: true
-        }
+        let mut tiled = Tiled::new_0(direction);
+        tiled.insert_child(0, TiledChild::Window(window));
+        tiled
     }
 
     pub fn new_2(window1: ManagedWindow, window2: ManagedWindow, direction: TiledDirection) -> Tiled {
-        let mut vec = Vec::with_capacity(2);
-        vec.push(TiledChild::Window(window1));
-        vec.push(TiledChild::Window(window2));
-        Tiled {
-            children: vec,
-            direction,
-            offset: 0,
-            dirty: true
-        }
+        let mut tiled = Tiled::new_0(direction);
+        tiled.insert_child(0, TiledChild::Window(window1));
+        tiled.insert_child(1, TiledChild::Window(window2));
+        tiled
     }
 
     /** Marks this grid as dirty. */
@@ -56,13 +219,78 @@ impl Tiled {
         return self.dirty;
     }
 
-    /** Swaps the position of window_0 and window_1, or does nothing if window_1 is empty. */
-    pub fn swap(&mut self) {
-        if self.children.len() == 2 {
-            self.children.swap(0, 1);
+    /** Swaps the position of two children, along with their sizes. */
+    pub fn swap(&mut self, first: usize, second: usize) {
+        self.children.swap(first, second);
+        self.weights.swap(first, second);
+
+        self.mark_dirty();
+    }
+
+    /**
+     * Inserts a child at the given index.
+     * The new child gets the average weight of its siblings, so it takes an even share of the space.
+     */
+    fn insert_child(&mut self, index: usize, child: TiledChild) {
+        let weight = if self.weights.len() == 0 {
+            1.0
+        } else {
+            self.weights.iter().sum::<f32>() / self.weights.len() as f32
+        };
+
+        self.children.insert(index, child);
+        self.weights.insert(index, weight);
+    }
+
+    /** Removes the child at the given index, along with its weight. */
+    fn remove_child(&mut self, index: usize) -> TiledChild {
+        if self.active > index || self.active == self.children.len() - 1 {
+            self.active = self.active.saturating_sub(1);
+        }
+
+        self.weights.remove(index);
+        return self.children.remove(index);
+    }
+
+    /** Returns the child shown in tabbed and stacked mode: the one with the focused window, or the last one that had it. */
+    fn active_index(&self) -> usize {
+        for (i, child) in self.children.iter().enumerate() {
+            let focused = match child {
+                TiledChild::Window(wrapped) => wrapped.focused,
+                TiledChild::Tiled(tiled) => tiled.get_focused().is_some()
+            };
+
+            if focused {
+                return i;
+            }
+        }
+
+        return self.active;
+    }
+
+    /** Returns the container at the end of the given indexes. */
+    fn container_mut(&mut self, path: &[usize]) -> &mut Tiled {
+        if path.len() == 0 {
+            return self;
         }
 
+        return self.children[path[0]].get_tiled().container_mut(&path[1..]);
+    }
+
+    /**
+     * Sets the mode of the container holding the focused window.
+     * Returns true if there is a focused window. This Tiled may be dirty after this request.
+     */
+    pub fn set_mode(&mut self, mode: TiledMode) -> bool {
+        let mut directions = Vec::with_capacity(8);
+        if !self.locate_focused(&mut directions) {
+            return false;
+        }
+
+        directions.pop();
+        self.container_mut(&directions).mode = mode;
         self.mark_dirty();
+        return true;
     }
 
     /**
@@ -72,7 +300,7 @@ impl Tiled {
     pub fn add(&mut self, mut window: ManagedWindow, direction: TiledDirection) {
         // If there is no children, just add it (will only happen for the windows directly under a workspace)
         if self.children.len() == 0 {
-            self.children.push(TiledChild::Window(window));
+            self.insert_child(0, TiledChild::Window(window));
             self.direction = direction;
 
             self.mark_dirty();
@@ -83,10 +311,7 @@ impl Tiled {
         let mut directions = Vec::with_capacity(8); // Default to 8 layers. Might be more, but that would be a LOT of windows in one workspace.
         if !self.locate_focused(&mut directions) {
             eprintln!("WARNING: Failed to locate focused window, but windows exist. Adding to first.");
-            if self.add_emergency(window, direction).is_err() {
-                eprintln!("ERROR: Window not created. What the fuck happened? Did you throw your computer in the ocean before running this? Or did I fuck something up that bad? In any case, please submit an issue. And maybe call an exorcist.");
-            }
-
+            self.add_emergency(window, direction);
             return;
         }
 
@@ -96,16 +321,25 @@ impl Tiled {
     }
 
     /**
-     * Adds a window from the given directions
+     * Adds a window from the given directions.
+     * If the focused window's container already splits in the given direction the window is added next to it,
+     * otherwise the focused window is split into a new container.
      */
     fn add_from_directions(&mut self, mut window: ManagedWindow, direction: TiledDirection, directions: &Vec<usize>, pos: usize) {
         if pos == directions.len() - 1 {
-            let mut focused = self.children.remove(directions[pos]).unwrap_window(); // Assumes directions are correct
-            focused.focused = false;
+            let index = directions[pos];
+            self.children[index].get_window().focused = false; // Assumes directions are correct
             window.focused = true;
-            let mut new = Tiled::new_2(focused, window, direction);
-            new.mark_dirty();
-            self.children.insert(directions[pos], TiledChild::Tiled(new));
+
+            if self.direction == direction || self.children.len() == 1 {
+                self.direction = direction;
+                self.insert_child(index + 1, TiledChild::Window(window));
+            } else {
+                // Replace the focused window with the new container, keeping its weight
+                let focused = self.children.remove(index).unwrap_window();
+                let new = Tiled::new_2(focused, window, direction);
+                self.children.insert(index, TiledChild::Tiled(new));
+            }
         } else {
             match self.children.get_mut(directions[pos]) {
                 Some(tiled) => tiled.get_tiled().add_from_directions(window, direction, directions, pos + 1), // Assumes directions are correct
@@ -117,45 +351,18 @@ impl Tiled {
     }
 
     /**
-     * Attempts to add when there is no focused window, but a window exists.
+     * Adds a window when there is no focused window, but a window exists.
+     * The window is added after the last child of this Tiled.
      */
-    fn add_emergency(&mut self, mut window: ManagedWindow, direction: TiledDirection) -> Result<(), ManagedWindow> {
-        if self.children.len() == 2 {
-            for index in 0..self.children.len() {
-                let child = self.children.remove(index);
-
-                if child.is_window() {
-                    let mut old = child.unwrap_window();
-                    old.focused = false;
-                    window.focused = true;
-                    let mut new = Tiled::new_2(old, window, direction);
-                    new.mark_dirty();
-                    self.children.push(TiledChild::Tiled(new));
-
-                    self.mark_dirty();
-                    return Ok(());
-                } else { // child.is_tiled()
-                    let mut tiled = child.unwrap_tiled();
-                    let res = tiled.add_emergency(window, direction);
-
-                    if res.is_ok() {
-                        self.mark_dirty();
-                        return Ok(());
-                    } else { // res.is_err()
-                        window = res.unwrap_err();
-                        self.children.insert(index, TiledChild::Tiled(tiled));
-                    }
-                }
-            }
-        } else {
-            self.children.push(TiledChild::Window(window));
+    fn add_emergency(&mut self, mut window: ManagedWindow, direction: TiledDirection) {
+        if self.children.len() == 1 {
             self.direction = direction;
-
-            self.mark_dirty();
-            return Ok(());
         }
 
-        return Err(window);
+        window.focused = true;
+        let index = self.children.len();
+        self.insert_child(index, TiledChild::Window(window));
+        self.mark_dirty();
     }
 
     /**
@@ -185,11 +392,298 @@ impl Tiled {
         return false;
     }
 
-    /** Removes the window given its ID. Returns true if something was removed. */
-    pub fn remove(&mut self, wid: u32) -> bool {
-        // TODO: Focus new window when this one gets removed
+    /** Finds the window with the given ID, adding the index of each child on the way to it to directions. */
+    fn locate_window(&self, wid: u32, directions: &mut Vec<usize>) -> bool {
+        for (i, child) in self.children.iter().enumerate() {
+            directions.push(i);
+
+            let found = match child {
+                TiledChild::Window(window) => window.window.wid == wid,
+                TiledChild::Tiled(tiled) => tiled.locate_window(wid, directions)
+            };
+            if found {
+                return true;
+            }
+
+            directions.pop();
+        }
+
+        return false;
+    }
+
+    /** Returns the child at the end of the given directions. */
+    fn child_mut(&mut self, directions: &[usize]) -> &mut TiledChild {
+        let (last, containers) = directions.split_last().unwrap();
+        return &mut self.container_mut(containers).children[*last];
+    }
+
+    /**
+     * Swaps two windows, wherever they are in the tree. Each takes the other's place and size.
+     * Returns true if both were found.
+     */
+    pub fn swap_windows(&mut self, first: u32, second: u32) -> bool {
+        let mut first_directions = Vec::with_capacity(8);
+        let mut second_directions = Vec::with_capacity(8);
+        if first == second || !self.locate_window(first, &mut first_directions) || !self.locate_window(second, &mut second_directions) {
+            return false;
+        }
+
+        // Both children can't be borrowed at once, so pass them through a placeholder
+        let placeholder = TiledChild::Tiled(Tiled::new_0(self.direction));
+        let first_child = replace(self.child_mut(&first_directions), placeholder);
+        let second_child = replace(self.child_mut(&second_directions), first_child);
+        *self.child_mut(&first_directions) = second_child;
+
+        self.mark_dirty();
+        return true;
+    }
+
+    /**
+     * Grows the focused window by amount pixels (or shrinks it if amount is negative) along the given direction.
+     * The nearest ancestor split in that direction is the one that gets resized.
+     * area is the area this Tiled fills, used to convert pixels to weights.
+     * Returns true if a split was found. This Tiled may be dirty after this request.
+     */
+    pub fn resize(&mut self, direction: TiledDirection, amount: i16, area: Geometry) -> bool {
+        let mut directions = Vec::with_capacity(8);
+        if !self.locate_focused(&mut directions) {
+            return false;
+        }
+
+        return self.resize_from_directions(direction, amount, &directions, 0, area);
+    }
+
+    /**
+     * Resizes the deepest split along the given directions that matches the given direction.
+     * The focused child takes space from (or gives space to) its next sibling, or its previous sibling if it is last.
+     */
+    fn resize_from_directions(&mut self, direction: TiledDirection, amount: i16, directions: &Vec<usize>, pos: usize, area: Geometry) -> bool {
+        // Children are closer to the focused window, so give them the first chance
+        if pos < directions.len() - 1 {
+            let child_area = self.child_areas(area)[directions[pos]];
+            let resized = match self.children.get_mut(directions[pos]) {
+                Some(tiled) => tiled.get_tiled().resize_from_directions(direction, amount, directions, pos + 1, child_area), // Assumes directions are correct
+                None => unreachable!()
+            };
+
+            if resized {
+                self.mark_dirty();
+                return true;
+            }
+        }
+
+        if self.mode != TiledMode::Split || self.direction != direction || self.children.len() < 2 {
+            return false;
+        }
+
+        let size = match self.direction {
+            TiledDirection::Vertical => area.height,
+            TiledDirection::Horizontal => area.width
+        };
+        if size == 0 {
+            return true;
+        }
+
+        let index = directions[pos];
+        let neighbour = if index + 1 < self.children.len() { index + 1 } else { index - 1 };
+
+        // Convert pixels to weights, keeping both children at least as big as a title bar
+        let per_pixel = self.weights.iter().sum::<f32>() / size as f32;
+        let min = MIN_TILE_SIZE as f32 * per_pixel;
+        let pair = self.weights[index] + self.weights[neighbour];
+        if pair < min * 2.0 {
+            return true;
+        }
+
+        let mut weight = self.weights[index] + amount as f32 * per_pixel;
+        if weight < min {
+            weight = min;
+        } else if weight > pair - min {
+            weight = pair - min;
+        }
+
+        self.weights[index] = weight;
+        self.weights[neighbour] = pair - weight;
+
+        self.mark_dirty();
+        return true;
+    }
+
+    /**
+     * Returns true if resize() would grow the given window by moving its left or top edge, which happens when it is
+     * (inside) the last child of the split that gets resized. Returns None if no split would be resized.
+     */
+    pub fn resizes_backwards(&self, wid: u32, direction: TiledDirection) -> Option<bool> {
+        let mut directions = Vec::with_capacity(8);
+        if !self.locate_window(wid, &mut directions) {
+            return None;
+        }
+
+        return self.resizes_backwards_from_directions(direction, &directions, 0);
+    }
+
+    /** Finds the split resize_from_directions() would resize, and returns true if the directions go through its last child. */
+    fn resizes_backwards_from_directions(&self, direction: TiledDirection, directions: &Vec<usize>, pos: usize) -> Option<bool> {
+        // Same order as resize_from_directions
+        if pos < directions.len() - 1 {
+            match self.children[directions[pos]] {
+                TiledChild::Tiled(ref tiled) => match tiled.resizes_backwards_from_directions(direction, directions, pos + 1) {
+                    Some(backwards) => return Some(backwards),
+                    None => ()
+                },
+                TiledChild::Window(_) => unreachable!() // Assumes directions are correct
+            };
+        }
+
+        if self.mode != TiledMode::Split || self.direction != direction || self.children.len() < 2 {
+            return None;
+        }
+
+        return Some(directions[pos] + 1 == self.children.len());
+    }
+
+    /**
+     * Removes the window given its ID, returning it if it was found.
+     * If the removed window was focused, the first window is focused instead.
+     */
+    pub fn remove(&mut self, wid: u32) -> Option<ManagedWindow> {
+        let removed = self.remove_from_children(wid);
+        self.hoist();
+
+        if removed.is_some() && self.children.len() > 0 && self.get_focused().is_none() {
+            self.set_first_focused();
+        }
+
+        return removed;
+    }
+
+    /** If this Tiled only holds a single container, that container takes its place. Used directly under a workspace. */
+    fn hoist(&mut self) {
+        if self.children.len() == 1 && self.children[0].is_tiled() {
+            *self = self.remove_child(0).unwrap_tiled();
+            self.mark_dirty();
+        }
+    }
+
+    /** Replaces containers that only have a single child left with that child, all the way down. */
+    fn collapse(&mut self) {
+        for i in 0..self.children.len() {
+            let single = match &mut self.children[i] {
+                TiledChild::Tiled(tiled) => {
+                    tiled.collapse();
+                    tiled.children.len() == 1
+                },
+                _ => false
+            };
+
+            if single {
+                // Keeps the container's weight
+                let mut tiled = self.children.remove(i).unwrap_tiled();
+                self.children.insert(i, tiled.children.remove(0));
+                self.mark_dirty();
+            }
+        }
+    }
+
+    /** Returns the direction children are laid out in. Tabs go left to right, and stacked title bars go top to bottom. */
+    fn axis(&self) -> TiledDirection {
+        match self.mode {
+            TiledMode::Split => self.direction,
+            TiledMode::Tabbed => TiledDirection::Horizontal,
+            TiledMode::Stacked => TiledDirection::Vertical
+        }
+    }
+
+    /**
+     * Moves the focused window in the given direction.
+     * Walks up from the focused window to the nearest container laid out along that direction. If the window is
+     * directly inside it, the window is swapped with its neighbour (or moved into it if the neighbour is a container),
+     * otherwise the window is moved out next to its ancestor.
+     * Returns true if the tree changed. This Tiled may be dirty after this request.
+     */
+    pub fn move_focused(&mut self, direction: Direction) -> bool {
+        let mut path = Vec::with_capacity(8);
+        if !self.locate_focused(&mut path) {
+            return false;
+        }
+
+        let wid = match self.get_focused() {
+            Some(wrapped) => wrapped.window.wid,
+            None => return false
+        };
+        let axis = direction.axis();
+        let forward = direction.is_forward();
+
+        for level in (0..path.len()).rev() {
+            let index = path[level];
+            let direct = level == path.len() - 1;
+            let container = self.container_mut(&path[..level]);
+
+            if container.axis() != axis {
+                continue;
+            }
+            if direct && ((forward && index + 1 >= container.children.len()) || (!forward && index == 0)) {
+                continue; // Already at the edge of this container
+            }
+
+            if direct {
+                let target = if forward { index + 1 } else { index - 1 };
+
+                if container.children[target].is_tiled() {
+                    let window = container.remove_child(index);
+                    let target = if forward { index } else { index - 1 }; // Shifted by the removal
+                    let tiled = container.children[target].get_tiled();
+                    let at = if forward { 0 } else { tiled.children.len() };
+                    tiled.insert_child(at, window);
+                    tiled.mark_dirty();
+                } else {
+                    container.swap(index, target);
+                }
+            } else {
+                // Containers left with a single child are collapsed in place, so index still points at the ancestor
+                let window = match container.remove_from_children(wid) {
+                    Some(window) => window,
+                    None => return false
+                };
+                container.insert_child(if forward { index + 1 } else { index }, TiledChild::Window(window));
+            }
+
+            self.collapse();
+            self.hoist();
+            self.mark_dirty();
+            return true;
+        }
+
+        // Nothing is laid out along that direction, so split the whole workspace along it with the window at the edge
+        if self.axis() == axis || self.children.len() < 2 {
+            return false;
+        }
+
+        let window = match self.remove_from_children(wid) {
+            Some(window) => window,
+            None => return false
+        };
+        self.hoist();
+
+        let mut old = replace(self, Tiled::new_0(axis));
+        if old.children.len() == 1 {
+            self.insert_child(0, old.remove_child(0));
+        } else {
+            self.insert_child(0, TiledChild::Tiled(old));
+        }
+
+        let at = if forward { 1 } else { 0 };
+        self.insert_child(at, TiledChild::Window(window));
+        return true;
+    }
+
+    /**
+     * Removes the window given its ID from this Tiled or its children.
+     * Containers that are left with a single child are replaced by that child.
+     */
+    fn remove_from_children(&mut self, wid: u32) -> Option<ManagedWindow> {
         let mut index = usize::max_value();
-        let mut tiled_children = 0;
+        let mut removed = None;
 
         for (i, child) in self.children.iter_mut().enumerate() {
             match child {
@@ -200,43 +694,29 @@ impl Tiled {
                     }
                 },
                 TiledChild::Tiled(tiled) => {
-                    if tiled.remove(wid) {
+                    removed = tiled.remove_from_children(wid);
+                    if removed.is_some() {
                         index = i;
-                        tiled_children = tiled.children.len(); // Will never be 0
                         break;
                     }
                 }
             };
         }
 
-        if tiled_children == 1 {
-            let mut tiled = self.children.remove(index).unwrap_tiled();
-            self.children.insert(index, tiled.children.remove(0));
-            self.set_first_focused();
-            self.mark_dirty();
-            return true;
-        } else if tiled_children == 2 {
-            self.mark_dirty();
-            return true; // Will be 0 if nothing was removed
+        if index == usize::max_value() {
+            return None;
         }
 
-        if index != usize::max_value() {
-            self.children.remove(index);
-
-            if self.children.len() == 1 {
-                if self.children[0].is_tiled() {
-                    let mut child = self.children.remove(0).unwrap_tiled();
-                    for _ in 0..child.children.len() {
-                        self.children.push(child.children.remove(0));
-                    }
-                }
-            }
-
-            self.mark_dirty();
-            return true;
+        if removed.is_none() { // Direct child
+            removed = Some(self.remove_child(index).unwrap_window());
+        } else if self.children[index].get_tiled().children.len() == 1 {
+            // Keeps the container's weight
+            let mut tiled = self.children.remove(index).unwrap_tiled();
+            self.children.insert(index, tiled.children.remove(0));
         }
 
-        return false;
+        self.mark_dirty();
+        return removed;
     }
 
     /** Returns the given window given its ID */
@@ -283,12 +763,13 @@ impl Tiled {
     pub fn set_focused(&mut self, wid: u32) -> bool {
         let mut found = false;
 
-        for win in self.children.iter_mut() {
+        for (i, win) in self.children.iter_mut().enumerate() {
             match win {
                 TiledChild::Window(wrapped) => {
                     if wrapped.window.wid == wid || wrapped.wrapper.wid == wid {
                         wrapped.focused = true;
                         found = true;
+                        self.active = i;
                     } else {
                         wrapped.focused = false;
                     }
@@ -296,6 +777,7 @@ impl Tiled {
                 TiledChild::Tiled(tiled) => {
                     if tiled.set_focused(wid) {
                         found = true;
+                        self.active = i;
                     }
                 }
             }
@@ -361,150 +843,292 @@ impl Tiled {
         return None;
     }
 
-    /** Positions the related windows, recursively tiling its children. */
-    pub fn tile(&mut self, client: &mut XClient, gc: &mut GraphicsContext, workspace_wid: u32, workspace_depth: u8, settings: &Settings, x: i16, y: i16, width: u16, height: u16, force: bool) {
-        // Ensure we should actually do this
-        if !self.dirty && !force {
-            return;
+    /** Splits length pixels between the children according to their weights. */
+    fn split_sizes(&self, length: u16) -> Vec<u16> {
+        let total = self.weights.iter().sum::<f32>();
+        let mut sizes = Vec::with_capacity(self.weights.len());
+        let mut used = 0;
+
+        for (i, weight) in self.weights.iter().enumerate() {
+            // The last child gets whatever rounding left over
+            let size = if i == self.weights.len() - 1 {
+                length - used
+            } else {
+                (length as f32 * weight / total) as u16
+            };
+
+            used += size;
+            sizes.push(size);
+        }
+
+        return sizes;
+    }
+
+    /**
+     * Splits the given area between the children.
+     * In tabbed and stacked mode every child gets the area below the title bars.
+     */
+    fn child_areas(&self, area: Geometry) -> Vec<Geometry> {
+        let bars = match self.mode {
+            TiledMode::Split => 0,
+            TiledMode::Tabbed => TITLE_HEIGHT,
+            TiledMode::Stacked => TITLE_HEIGHT * self.children.len() as u16
+        };
+        if self.mode != TiledMode::Split {
+            let body = Geometry { x: area.x, y: area.y + bars as i16, width: area.width, height: area.height.saturating_sub(bars) };
+            return vec![body; self.children.len()];
         }
+
+        let sizes = match self.direction {
+            TiledDirection::Vertical => self.split_sizes(area.height),
+            TiledDirection::Horizontal => self.split_sizes(area.width)
+        };
+
+        let mut areas = Vec::with_capacity(sizes.len());
+        let mut pos = 0;
+        for size in sizes {
+            areas.push(match self.direction {
+                TiledDirection::Vertical => Geometry { x: area.x, y: area.y + pos as i16, width: area.width, height: size },
+                TiledDirection::Horizontal => Geometry { x: area.x + pos as i16, y: area.y, width: size, height: area.height }
+            });
+            pos += size;
+        }
+
+        return areas;
+    }
+
+    /**
+     * Computes where every window should be placed when this Tiled fills the given area.
+     * This doesn't talk to the X server, see tile() for that.
+     */
+    pub fn layout(&self, settings: &Settings, area: Geometry) -> Vec<TileAssignment> {
+        let mut assignments = Vec::new();
+        self.layout_into(settings, area, &mut assignments);
+        return assignments;
+    }
+
+    /** Recursive part of layout(). */
+    fn layout_into(&self, settings: &Settings, area: Geometry, assignments: &mut Vec<TileAssignment>) {
         if self.children.len() == 0 {
             return;
+        }
+
+        if self.mode == TiledMode::Split {
+            for (child, child_area) in self.children.iter().zip(self.child_areas(area)) {
+                match child {
+                    TiledChild::Window(wrapped) => assignments.push(TileAssignment::new(settings, wrapped.window.wid, child_area)),
+                    TiledChild::Tiled(tiled) => tiled.layout_into(settings, child_area, assignments)
+                };
+            }
+
+            return;
+        }
+
+        // Tabbed and stacked: the active child goes first so the other title bars are stacked on top of it
+        let count = self.children.len();
+        let active = self.active_index();
+        let tab_widths = split_even(area.width, count);
+        let tab_x = |index: usize| tab_widths[..index].iter().sum::<u16>() as i16;
+        let bars_height = TITLE_HEIGHT * count as u16;
+
+        match &self.children[active] {
+            TiledChild::Window(wrapped) => {
+                let mut assignment = match self.mode {
+                    TiledMode::Tabbed => {
+                        let mut assignment = TileAssignment::new(settings, wrapped.window.wid, area);
+                        assignment.title = Geometry { x: tab_x(active), y: 0, width: tab_widths[active], height: TITLE_HEIGHT };
+                        assignment
+                    },
+                    _ => {
+                        // The wrapper starts at its own bar, and the window goes below every bar
+                        let before = TITLE_HEIGHT * active as u16;
+                        let after = bars_height - before - TITLE_HEIGHT;
+                        let wrapper = Geometry { x: area.x, y: area.y + before as i16, width: area.width, height: area.height.saturating_sub(before) };
+
+                        let mut assignment = TileAssignment::new(settings, wrapped.window.wid, wrapper);
+                        assignment.window = client_geometry(settings, wrapper.width, wrapper.height.saturating_sub(after));
+                        assignment.window.y += after as i16;
+                        assignment
+                    }
+                };
+
+                assignment.active = true;
+                assignments.push(assignment);
+            },
+            // Containers don't have a title bar of their own, so their bar is left empty while they are shown
+            TiledChild::Tiled(tiled) => tiled.layout_into(settings, self.child_areas(area)[active], assignments)
         };
 
-        // Tile
-        if self.children.len() == 1 { // Fill
-            match &mut self.children[0] {
-                TiledChild::Window(wrapped) => {
-                    wrapped.wrapper.configure_multiple(
-                        client,
-                        vec![
-                            WindowConfigureValue::X(x),
-                            WindowConfigureValue::Y(y),
-                            WindowConfigureValue::Width(width),
-                            WindowConfigureValue::Height(height)
-                        ]
-                    );
-                    wrapped.window.configure_multiple(
-                        client,
-                        vec![
-                            WindowConfigureValue::X(0),
-                            WindowConfigureValue::Y(20),
-                            WindowConfigureValue::Width(width - 1),
-                            WindowConfigureValue::Height(height - 20 - 1)
-                        ]
-                    );
-
-                    // Tell the X Server to update this window
-                    client.send_event(&ServerEvent::Expose {
-                        window: wrapped.wrapper.wid,
-                        x: 0,
-                        y: 0,
-                        width: wrapped.wrapper.width,
-                        height: wrapped.wrapper.height,
-                        count: 0
-                    }, false, wrapped.wrapper.wid, &vec![]);
-                    //wrapped.paint(client, gc, workspace_wid, workspace_depth, &settings)
-                },
-                TiledChild::Tiled(child) => {
-                    child.tile(client, gc, workspace_wid, workspace_depth, &settings, x, y, width, height, force);
-                }
+        // Everything else only shows its title bar. Containers are represented by their focused (or first) window.
+        for (i, child) in self.children.iter().enumerate() {
+            if i == active {
+                continue;
             }
-        } else {
-            // Get the children and their positions
-            let (
-                first_x, first_y, first_width, first_height,
-                second_x, second_y, second_width, second_height
-            ) = match self.direction {
-                TiledDirection::Vertical => {(
-                        x, y, width, (height as i16 / 2 + self.offset) as u16,
-                        x, y + height as i16 / 2 + self.offset, width, (height as i16 / 2 - self.offset) as u16
-                    )
-                },
-                TiledDirection::Horizontal => {
-                    (
-                        x, y, (width as i16 / 2 + self.offset) as u16, height,
-                        x + width as i16 / 2 + self.offset, y, (width as i16 / 2 - self.offset) as u16, height
-                    )
+
+            let representative = match child {
+                TiledChild::Window(wrapped) => wrapped,
+                TiledChild::Tiled(tiled) => match tiled.get_focused() {
+                    Some(wrapped) => wrapped,
+                    None => tiled.first_window()
                 }
             };
 
-            // Apply changes
-            match &mut self.children[0] {
-                TiledChild::Window(wrapped) => {
-                    wrapped.wrapper.configure_multiple(
-                        client,
-                        vec![
-                            WindowConfigureValue::X(first_x),
-                            WindowConfigureValue::Y(first_y),
-                            WindowConfigureValue::Width(first_width),
-                            WindowConfigureValue::Height(first_height)
-                        ]
-                    );
-                    wrapped.window.configure_multiple(
-                        client,
-                        vec![
-                            WindowConfigureValue::X(0),
-                            WindowConfigureValue::Y(20),
-                            WindowConfigureValue::Width(first_width - 1),
-                            WindowConfigureValue::Height(first_height - 20 - 1)
-                        ]
-                    );
-
-                    // Tell the X Server to update this window
-                    client.send_event(&ServerEvent::Expose {
-                        window: wrapped.wrapper.wid,
-                        x: 0,
-                        y: 0,
-                        width: wrapped.wrapper.width,
-                        height: wrapped.wrapper.height,
-                        count: 0
-                    }, false, wrapped.wrapper.wid, &vec![]);
-                    //wrapped.paint(client, gc, workspace_wid, workspace_depth, &settings)
-                },
-                TiledChild::Tiled(child) => {
-                    child.tile(client, gc, workspace_wid, workspace_depth, &settings, first_x, first_y, first_width, first_height, force);
-                }
+            let bar = match self.mode {
+                TiledMode::Tabbed => Geometry { x: area.x + tab_x(i), y: area.y, width: tab_widths[i], height: TITLE_HEIGHT },
+                _ => Geometry { x: area.x, y: area.y + (TITLE_HEIGHT * i as u16) as i16, width: area.width, height: TITLE_HEIGHT }
             };
-            match &mut self.children[1] {
+
+            // Keep the window at the size it would be shown at, so switching tabs doesn't resize it
+            let body = self.child_areas(area)[i];
+            let mut assignment = TileAssignment::new(settings, representative.window.wid, bar);
+            assignment.window = client_geometry(settings, body.width, body.height + TITLE_HEIGHT);
+            assignments.push(assignment);
+
+            match child {
+                TiledChild::Tiled(tiled) => tiled.hide_into(representative.window.wid, assignments),
+                _ => ()
+            };
+        }
+    }
+
+    /** Returns the first window in this Tiled. Must not be empty. */
+    fn first_window(&self) -> &ManagedWindow {
+        match &self.children[0] {
+            TiledChild::Window(wrapped) => wrapped,
+            TiledChild::Tiled(tiled) => tiled.first_window()
+        }
+    }
+
+    /** Hides every window in this Tiled except the given one. */
+    fn hide_into(&self, except: u32, assignments: &mut Vec<TileAssignment>) {
+        for child in self.children.iter() {
+            match child {
                 TiledChild::Window(wrapped) => {
-                    wrapped.wrapper.configure_multiple(
-                        client,
-                        vec![
-                            WindowConfigureValue::X(second_x),
-                            WindowConfigureValue::Y(second_y),
-                            WindowConfigureValue::Width(second_width),
-                            WindowConfigureValue::Height(second_height)
-                        ]
-                    );
-                    wrapped.window.configure_multiple( // TODO: Take off border
-                        client,
-                        vec![
-                            WindowConfigureValue::X(0),
-                            WindowConfigureValue::Y(20),
-                            WindowConfigureValue::Width(second_width - 1),
-                            WindowConfigureValue::Height(second_height - 20 - 1)
-                        ]
-                    );
-
-                    // Tell the X Server to update this window
-                    client.send_event(&ServerEvent::Expose {
-                        window: wrapped.wrapper.wid,
-                        x: 0,
-                        y: 0,
-                        width: wrapped.wrapper.width,
-                        height: wrapped.wrapper.height,
-                        count: 0
-                    }, false, wrapped.wrapper.wid, &vec![]);
-                    //wrapped.paint(client, gc, workspace_wid, workspace_depth, &settings)
+                    if wrapped.window.wid != except {
+                        assignments.push(TileAssignment::hidden(wrapped.window.wid));
+                    }
                 },
-                TiledChild::Tiled(child) => {
-                    child.tile(client, gc, workspace_wid, workspace_depth, &settings, second_x, second_y, second_width, second_height, force);
-                }
+                TiledChild::Tiled(tiled) => tiled.hide_into(except, assignments)
             };
-        };
+        }
+    }
 
-        // Mark as clean
+    /** Marks this Tiled and all of its children as clean. */
+    fn mark_clean(&mut self) {
         self.dirty = false;
+
+        for child in self.children.iter_mut() {
+            match child {
+                TiledChild::Tiled(tiled) => tiled.mark_clean(),
+                _ => ()
+            };
+        }
+    }
+
+    /** Adds every window, in order, to windows. */
+    fn windows_into<'a>(&'a self, windows: &mut Vec<&'a ManagedWindow>) {
+        for child in self.children.iter() {
+            match child {
+                TiledChild::Window(wrapped) => windows.push(wrapped),
+                TiledChild::Tiled(tiled) => tiled.windows_into(windows)
+            };
+        }
+    }
+
+    /** Removes every window, in order, and adds them to windows. */
+    fn take_all_into(&mut self, windows: &mut Vec<ManagedWindow>) {
+        self.weights.clear();
+
+        for child in self.children.drain(..) {
+            match child {
+                TiledChild::Window(wrapped) => windows.push(wrapped),
+                TiledChild::Tiled(mut tiled) => tiled.take_all_into(windows)
+            };
+        }
+
+        self.mark_dirty();
+    }
+}
+
+impl Layout for Tiled {
+    fn kind(&self) -> LayoutKind {
+        return LayoutKind::Tiled;
+    }
+
+    fn add(&mut self, window: ManagedWindow, direction: TiledDirection) {
+        Tiled::add(self, window, direction);
+    }
+
+    fn remove(&mut self, wid: u32) -> Option<ManagedWindow> {
+        return Tiled::remove(self, wid);
+    }
+
+    fn take_all(&mut self) -> Vec<ManagedWindow> {
+        let mut windows = Vec::new();
+        self.take_all_into(&mut windows);
+        return windows;
+    }
+
+    fn windows(&self) -> Vec<&ManagedWindow> {
+        let mut windows = Vec::new();
+        self.windows_into(&mut windows);
+        return windows;
+    }
+
+    fn get_window(&self, wid: u32) -> Option<&ManagedWindow> {
+        return Tiled::get_window(self, wid);
+    }
+
+    fn get_window_mut(&mut self, wid: u32) -> Option<&mut ManagedWindow> {
+        return Tiled::get_window_mut(self, wid);
+    }
+
+    fn set_focused(&mut self, wid: u32) -> bool {
+        return Tiled::set_focused(self, wid);
+    }
+
+    fn get_focused(&self) -> Option<&ManagedWindow> {
+        return Tiled::get_focused(self);
+    }
+
+    fn swap_windows(&mut self, first: u32, second: u32) -> bool {
+        return Tiled::swap_windows(self, first, second);
+    }
+
+    fn get_focused_mut(&mut self) -> Option<&mut ManagedWindow> {
+        return Tiled::get_focused_mut(self);
+    }
+
+    fn resize(&mut self, direction: TiledDirection, amount: i16, area: Geometry) -> bool {
+        return Tiled::resize(self, direction, amount, area);
+    }
+
+    fn resizes_backwards(&self, wid: u32, direction: TiledDirection) -> Option<bool> {
+        return Tiled::resizes_backwards(self, wid, direction);
+    }
+
+    fn layout(&self, settings: &Settings, area: Geometry) -> Vec<TileAssignment> {
+        return Tiled::layout(self, settings, area);
+    }
+
+    fn mark_dirty(&mut self) {
+        Tiled::mark_dirty(self);
+    }
+
+    fn is_dirty(&self) -> bool {
+        return Tiled::is_dirty(self);
+    }
+
+    fn mark_clean(&mut self) {
+        Tiled::mark_clean(self);
+    }
+
+    fn set_mode(&mut self, mode: TiledMode) -> bool {
+        return Tiled::set_mode(self, mode);
+    }
+
+    fn move_focused(&mut self, direction: Direction, _neighbour: Option<u32>) -> bool {
+        return Tiled::move_focused(self, direction);
     }
 }
 
@@ -557,8 +1181,349 @@ impl TiledChild {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/** How a Tiled shows its children. */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TiledMode {
+    Split, // Side by side (or on top of each other) along the direction
+    Tabbed, // One child shown at a time, with a row of tabs
+    Stacked // One child shown at a time, with a column of title bars
+}
+
+/** A direction on the screen, used to move between windows. */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down
+}
+impl Direction {
+    /** Returns the direction a Tiled would need to be split in to move this way. */
+    pub fn axis(&self) -> TiledDirection {
+        match *self {
+            Direction::Left | Direction::Right => TiledDirection::Horizontal,
+            Direction::Up | Direction::Down => TiledDirection::Vertical
+        }
+    }
+
+    /** Returns true if moving this way goes towards later children. */
+    pub fn is_forward(&self) -> bool {
+        match *self {
+            Direction::Right | Direction::Down => true,
+            Direction::Left | Direction::Up => false
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TiledDirection {
     Vertical,
     Horizontal
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(x: i16, y: i16, width: u16, height: u16) -> Geometry {
+        Geometry { x, y, width, height }
+    }
+
+    fn window(wid: u32) -> TiledChild {
+        TiledChild::Window(ManagedWindow::detached(wid))
+    }
+
+    fn wrappers(assignments: &Vec<TileAssignment>) -> Vec<(u32, Geometry)> {
+        return assignments.iter().map(|assignment| (assignment.wid, assignment.wrapper)).collect();
+    }
+
+    /** Describes the tree by window IDs, containers in brackets. */
+    fn shape(tiled: &Tiled) -> String {
+        let children: Vec<String> = tiled.children.iter().map(|child| match child {
+            TiledChild::Window(wrapped) => wrapped.window.wid.to_string(),
+            TiledChild::Tiled(tiled) => format!("[{}]", shape(tiled))
+        }).collect();
+        return children.join(" ");
+    }
+
+    #[test]
+    fn client_geometry_subtracts_title_and_borders() {
+        let mut settings = Settings::default();
+        settings.win_border_width_left = 1;
+        settings.win_border_width_top = 2;
+        settings.win_border_width_right = 3;
+        settings.win_border_width_bottom = 4;
+
+        assert_eq!(client_geometry(&settings, 100, 80), area(1, 22, 96, 54));
+    }
+
+    #[test]
+    fn client_geometry_saturates_on_tiny_areas() {
+        let mut settings = Settings::default();
+        settings.win_border_width_left = 2;
+        settings.win_border_width_right = 2;
+
+        assert_eq!(client_geometry(&settings, 3, 10), area(2, TITLE_HEIGHT as i16, 1, 1));
+        assert_eq!(client_geometry(&settings, 0, 0), area(2, TITLE_HEIGHT as i16, 1, 1));
+    }
+
+    #[test]
+    fn split_sizes_give_rounding_to_the_last_child() {
+        let mut tiled = Tiled::new_0(TiledDirection::Horizontal);
+        for wid in 1..4 {
+            tiled.insert_child(0, window(wid));
+        }
+
+        assert_eq!(tiled.split_sizes(100), vec![33, 33, 34]);
+
+        tiled.weights = vec![1.0, 1.0, 2.0];
+        assert_eq!(tiled.split_sizes(100), vec![25, 25, 50]);
+    }
+
+    #[test]
+    fn child_areas_start_at_the_area_origin() {
+        let tiled = Tiled::new_2(ManagedWindow::detached(1), ManagedWindow::detached(2), TiledDirection::Vertical);
+
+        assert_eq!(tiled.child_areas(area(10, 5, 200, 100)), vec![area(10, 5, 200, 50), area(10, 55, 200, 50)]);
+    }
+
+    #[test]
+    fn layout_nested_splits_away_from_the_origin() {
+        // | 1 | 2 |
+        // |   |---|
+        // |   | 3 |
+        let mut tiled = Tiled::new_1(ManagedWindow::detached(1), TiledDirection::Horizontal);
+        let inner = Tiled::new_2(ManagedWindow::detached(2), ManagedWindow::detached(3), TiledDirection::Vertical);
+        tiled.insert_child(1, TiledChild::Tiled(inner));
+
+        let assignments = tiled.layout(&Settings::default(), area(10, 5, 200, 100));
+        assert_eq!(wrappers(&assignments), vec![
+            (1, area(10, 5, 100, 100)),
+            (2, area(110, 5, 100, 50)),
+            (3, area(110, 55, 100, 50))
+        ]);
+        assert!(assignments.iter().all(|assignment| assignment.visible && !assignment.active));
+    }
+
+    #[test]
+    fn layout_weights_nested_splits() {
+        let mut tiled = Tiled::new_1(ManagedWindow::detached(1), TiledDirection::Vertical);
+        let inner = Tiled::new_2(ManagedWindow::detached(2), ManagedWindow::detached(3), TiledDirection::Horizontal);
+        tiled.insert_child(1, TiledChild::Tiled(inner));
+        tiled.weights = vec![1.0, 3.0];
+
+        let assignments = tiled.layout(&Settings::default(), area(0, 0, 100, 200));
+        assert_eq!(wrappers(&assignments), vec![
+            (1, area(0, 0, 100, 50)),
+            (2, area(0, 50, 50, 150)),
+            (3, area(50, 50, 50, 150))
+        ]);
+    }
+
+    #[test]
+    fn layout_puts_clients_below_the_title_and_inside_borders() {
+        let mut settings = Settings::default();
+        settings.win_border_width_left = 1;
+        settings.win_border_width_top = 1;
+        settings.win_border_width_right = 1;
+        settings.win_border_width_bottom = 1;
+
+        let tiled = Tiled::new_2(ManagedWindow::detached(1), ManagedWindow::detached(2), TiledDirection::Horizontal);
+        let assignments = tiled.layout(&settings, area(0, 0, 200, 100));
+
+        for assignment in assignments.iter() {
+            assert_eq!(assignment.title, area(0, 0, 100, TITLE_HEIGHT));
+            assert_eq!(assignment.window, area(1, TITLE_HEIGHT as i16 + 1, 98, 100 - TITLE_HEIGHT - 2));
+        }
+    }
+
+    #[test]
+    fn layout_tabbed_shows_the_active_child_with_every_tab() {
+        let mut tiled = Tiled::new_2(ManagedWindow::detached(1), ManagedWindow::detached(2), TiledDirection::Horizontal);
+        tiled.mode = TiledMode::Tabbed;
+
+        let assignments = tiled.layout(&Settings::default(), area(10, 0, 200, 100));
+        assert_eq!(assignments.len(), 2);
+
+        // The active child fills the area, with its tab at the left of the bar
+        assert_eq!(assignments[0].wid, 1);
+        assert!(assignments[0].active);
+        assert_eq!(assignments[0].wrapper, area(10, 0, 200, 100));
+        assert_eq!(assignments[0].title, area(0, 0, 100, TITLE_HEIGHT));
+
+        // The other child is only its tab
+        assert_eq!(assignments[1].wid, 2);
+        assert_eq!(assignments[1].wrapper, area(110, 0, 100, TITLE_HEIGHT));
+    }
+
+    #[test]
+    fn resizes_backwards_follows_the_deepest_matching_split() {
+        // | 1 | 2 | 3 |, with 1 and 2 in their own split
+        let mut tiled = Tiled::new_1(ManagedWindow::detached(3), TiledDirection::Horizontal);
+        let inner = Tiled::new_2(ManagedWindow::detached(1), ManagedWindow::detached(2), TiledDirection::Horizontal);
+        tiled.insert_child(0, TiledChild::Tiled(inner));
+
+        // 2 has a window to its right, but resize() trades space with 1
+        assert_eq!(tiled.resizes_backwards(1, TiledDirection::Horizontal), Some(false));
+        assert_eq!(tiled.resizes_backwards(2, TiledDirection::Horizontal), Some(true));
+        assert_eq!(tiled.resizes_backwards(3, TiledDirection::Horizontal), Some(true));
+        assert_eq!(tiled.resizes_backwards(2, TiledDirection::Vertical), None);
+        assert_eq!(tiled.resizes_backwards(4, TiledDirection::Horizontal), None);
+    }
+
+    #[test]
+    fn find_neighbour_prefers_overlapping_windows() {
+        let settings = Settings::default();
+        let assignments = vec![
+            TileAssignment::new(&settings, 1, area(0, 0, 100, 60)),
+            TileAssignment::new(&settings, 2, area(100, 0, 100, 50)),
+            TileAssignment::new(&settings, 3, area(100, 50, 100, 50)),
+            TileAssignment::hidden(4)
+        ];
+
+        assert_eq!(find_neighbour(&assignments, 1, Direction::Right), Some(2));
+        assert_eq!(find_neighbour(&assignments, 1, Direction::Left), None);
+        assert_eq!(find_neighbour(&assignments, 2, Direction::Down), Some(3));
+        assert_eq!(find_neighbour(&assignments, 3, Direction::Left), Some(1));
+        assert_eq!(find_neighbour(&assignments, 3, Direction::Up), Some(2));
+        assert_eq!(find_neighbour(&assignments, 4, Direction::Left), None); // Hidden windows have no neighbours
+    }
+
+    #[test]
+    fn split_even_gives_the_rest_to_the_last_part() {
+        assert_eq!(split_even(10, 3), vec![3, 3, 4]);
+        assert_eq!(split_even(9, 3), vec![3, 3, 3]);
+        assert_eq!(split_even(5, 1), vec![5]);
+    }
+
+    #[test]
+    fn resize_trades_weight_with_the_neighbour() {
+        let mut tiled = Tiled::new_2(ManagedWindow::detached(1), ManagedWindow::detached(2), TiledDirection::Horizontal);
+        tiled.set_focused(1);
+
+        // 256px over a total weight of 2, so every 128px is worth 1
+        assert!(tiled.resize(TiledDirection::Horizontal, 32, area(0, 0, 256, 100)));
+        assert_eq!(tiled.weights, vec![1.25, 0.75]);
+
+        // The last child trades with the one before it
+        tiled.set_focused(2);
+        assert!(tiled.resize(TiledDirection::Horizontal, 64, area(0, 0, 256, 100)));
+        assert_eq!(tiled.weights, vec![0.75, 1.25]);
+    }
+
+    #[test]
+    fn resize_keeps_both_children_at_the_minimum_size() {
+        let mut tiled = Tiled::new_2(ManagedWindow::detached(1), ManagedWindow::detached(2), TiledDirection::Horizontal);
+        tiled.set_focused(1);
+
+        assert!(tiled.resize(TiledDirection::Horizontal, 500, area(0, 0, 256, 100)));
+        assert_eq!(tiled.split_sizes(256), vec![256 - MIN_TILE_SIZE, MIN_TILE_SIZE]);
+
+        assert!(tiled.resize(TiledDirection::Horizontal, -1000, area(0, 0, 256, 100)));
+        assert_eq!(tiled.split_sizes(256), vec![MIN_TILE_SIZE, 256 - MIN_TILE_SIZE]);
+    }
+
+    #[test]
+    fn resize_at_an_outer_edge_does_nothing() {
+        let mut tiled = Tiled::new_2(ManagedWindow::detached(1), ManagedWindow::detached(2), TiledDirection::Horizontal);
+        tiled.set_focused(2);
+
+        // Nothing is split vertically, so both vertical edges are the workspace's
+        assert!(!tiled.resize(TiledDirection::Vertical, 32, area(0, 0, 256, 100)));
+        assert_eq!(tiled.weights, vec![1.0, 1.0]);
+
+        let mut alone = Tiled::new_1(ManagedWindow::detached(1), TiledDirection::Horizontal);
+        alone.set_focused(1);
+        assert!(!alone.resize(TiledDirection::Horizontal, 32, area(0, 0, 256, 100)));
+        assert_eq!(alone.weights, vec![1.0]);
+    }
+
+    #[test]
+    fn add_in_the_same_direction_makes_one_split() {
+        let mut tiled = Tiled::new_0(TiledDirection::Vertical);
+        for wid in 1..4 {
+            tiled.add(ManagedWindow::detached(wid), TiledDirection::Horizontal);
+        }
+
+        assert_eq!(shape(&tiled), "1 2 3");
+        assert_eq!(tiled.split_sizes(300), vec![100, 100, 100]);
+
+        // Another direction splits the focused window instead
+        tiled.add(ManagedWindow::detached(4), TiledDirection::Vertical);
+        assert_eq!(shape(&tiled), "1 2 [3 4]");
+    }
+
+    #[test]
+    fn remove_collapses_single_child_containers() {
+        let mut tiled = Tiled::new_0(TiledDirection::Horizontal);
+        for wid in 1..4 {
+            tiled.add(ManagedWindow::detached(wid), TiledDirection::Horizontal);
+        }
+        tiled.add(ManagedWindow::detached(4), TiledDirection::Vertical);
+
+        assert_eq!(tiled.remove(4).map(|wrapped| wrapped.window.wid), Some(4));
+        assert_eq!(shape(&tiled), "1 2 3");
+        assert_eq!(tiled.get_focused().map(|wrapped| wrapped.window.wid), Some(1)); // The removed window had the focus
+        assert!(tiled.remove(9).is_none());
+
+        // A workspace left with a single container becomes that container
+        let mut tiled = Tiled::new_1(ManagedWindow::detached(1), TiledDirection::Horizontal);
+        let inner = Tiled::new_2(ManagedWindow::detached(2), ManagedWindow::detached(3), TiledDirection::Vertical);
+        tiled.insert_child(1, TiledChild::Tiled(inner));
+
+        tiled.remove(1);
+        assert_eq!(shape(&tiled), "2 3");
+        assert_eq!(tiled.direction, TiledDirection::Vertical);
+    }
+
+    #[test]
+    fn move_focused_swaps_with_a_sibling() {
+        let mut tiled = Tiled::new_2(ManagedWindow::detached(1), ManagedWindow::detached(2), TiledDirection::Horizontal);
+        tiled.weights = vec![3.0, 1.0];
+        tiled.set_focused(1);
+
+        assert!(tiled.move_focused(Direction::Right));
+        assert_eq!(shape(&tiled), "2 1");
+        assert_eq!(tiled.weights, vec![1.0, 3.0]); // The window keeps its size
+    }
+
+    #[test]
+    fn move_focused_leaves_a_nested_container() {
+        // | 1 | 2 |
+        // |   |---|
+        // |   | 3 |
+        let mut tiled = Tiled::new_1(ManagedWindow::detached(1), TiledDirection::Horizontal);
+        let inner = Tiled::new_2(ManagedWindow::detached(2), ManagedWindow::detached(3), TiledDirection::Vertical);
+        tiled.insert_child(1, TiledChild::Tiled(inner));
+        tiled.set_focused(3);
+
+        // The emptied container collapses into its last window
+        assert!(tiled.move_focused(Direction::Right));
+        assert_eq!(shape(&tiled), "1 2 3");
+        assert_eq!(tiled.get_focused().map(|wrapped| wrapped.window.wid), Some(3));
+    }
+
+    #[test]
+    fn move_focused_at_the_edge_does_nothing() {
+        let mut tiled = Tiled::new_2(ManagedWindow::detached(1), ManagedWindow::detached(2), TiledDirection::Horizontal);
+        tiled.set_focused(2);
+
+        assert!(!tiled.move_focused(Direction::Right));
+        assert_eq!(shape(&tiled), "1 2");
+        assert_eq!(tiled.weights, vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn swap_windows_trades_places_across_containers() {
+        let mut tiled = Tiled::new_1(ManagedWindow::detached(1), TiledDirection::Horizontal);
+        let inner = Tiled::new_2(ManagedWindow::detached(2), ManagedWindow::detached(3), TiledDirection::Vertical);
+        tiled.insert_child(1, TiledChild::Tiled(inner));
+
+        assert!(tiled.swap_windows(1, 3));
+        assert_eq!(shape(&tiled), "3 [2 1]");
+
+        assert!(!tiled.swap_windows(1, 1));
+        assert!(!tiled.swap_windows(1, 9));
+        assert_eq!(shape(&tiled), "3 [2 1]");
+    }
+}